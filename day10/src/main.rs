@@ -3,7 +3,7 @@ use array2d::Array2D;
 use std::fs::read_to_string;
 use std::path::Path;
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Dir {
     Up,
     Down,
@@ -11,6 +11,30 @@ enum Dir {
     Right,
 }
 
+const ALL_DIRS: [Dir; 4] = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
+
+impl Dir {
+    fn opposite(&self) -> Dir {
+        match self {
+            Dir::Up => Dir::Down,
+            Dir::Down => Dir::Up,
+            Dir::Left => Dir::Right,
+            Dir::Right => Dir::Left,
+        }
+    }
+
+    /// Returns the position of the neighbor in this direction, or None if it is outside of the field.
+    fn neighbor(&self, field: &Field, pos: Position) -> Option<Position> {
+        match self {
+            Dir::Up if pos.0 > 0 => Some((pos.0 - 1, pos.1)),
+            Dir::Down if pos.0 + 1 < field.num_rows() => Some((pos.0 + 1, pos.1)),
+            Dir::Left if pos.1 > 0 => Some((pos.0, pos.1 - 1)),
+            Dir::Right if pos.1 + 1 < field.num_columns() => Some((pos.0, pos.1 + 1)),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq)]
 enum UpscaledTile {
     Inside,
@@ -87,9 +111,9 @@ fn calc_enclosed_tiles(field: &Field, start_pos: (usize, usize)) -> usize {
 }
 
 fn calc_steps_to_farthest_point(field: &Field, start_pos: Position) -> Vec<Position> {
-    // Determine any valid direction from the start field
+    // Take any of the two directions of the (inferred) start tile
     let mut all_pos = vec![start_pos];
-    let mut next_dir = Dir::Down; // Guessed - for a generic solution, determine this by looking at the start's neighbors
+    let mut next_dir = field.get(start_pos.0, start_pos.1).unwrap()[0];
 
     // Start in any direction from start field and keep going until we reach the start field again
     while all_pos.len() == 1 || *all_pos.last().unwrap() != start_pos {
@@ -142,6 +166,29 @@ fn calc_steps_to_farthest_point(field: &Field, start_pos: Position) -> Vec<Posit
     all_pos
 }
 
+/// Infers the shape of the start tile from the neighbors whose pipes connect back to it. Exactly two neighbors have to connect,
+/// otherwise the shape is either impossible (less) or ambiguous (more).
+fn infer_start_tile(field: &Field, start_pos: Position) -> Result<PipeField> {
+    let dirs: PipeField = ALL_DIRS
+        .iter()
+        .filter(|dir| {
+            dir.neighbor(field, start_pos)
+                .map(|(row, column)| field.get(row, column).unwrap().contains(&dir.opposite()))
+                .unwrap_or(false)
+        })
+        .cloned()
+        .collect();
+
+    match dirs.len() {
+        2 => Ok(dirs),
+        0 | 1 => Err(anyhow!(
+            "Start tile is impossible, only connected to {:?}!",
+            dirs
+        )),
+        _ => Err(anyhow!("Start tile is ambiguous, connected to {:?}!", dirs)),
+    }
+}
+
 fn flood_fill(f: &mut UpscaledField, row: usize, column: usize) {
     if *f.get(row, column).unwrap() != UpscaledTile::Inside {
         return;
@@ -197,26 +244,26 @@ fn read_input_file<P: AsRef<Path>>(input_path: P) -> Result<(Field, Position)> {
                     '7' => vec![Dir::Down, Dir::Left],
                     'F' => vec![Dir::Down, Dir::Right],
                     '.' => vec![],
-                    'S' => {
-                        vec![Dir::Up, Dir::Down, Dir::Left, Dir::Right]
-                    }
+                    // Shape of the start tile is inferred below
+                    'S' => vec![],
                     _ => panic!("Unknown character in input!"),
                 })
                 .collect()
         })
         .collect();
-    let field = Array2D::from_rows(&field_vec).unwrap();
+    let mut field = Array2D::from_rows(&field_vec).unwrap();
 
     // Find start position on field
-    for row in 0..field.num_rows() {
-        for column in 00..field.num_columns() {
-            if *field.get(row, column).unwrap() == vec![Dir::Up, Dir::Down, Dir::Left, Dir::Right] {
-                return Ok((field, (row, column)));
-            }
-        }
-    }
+    let start_pos = input
+        .lines()
+        .enumerate()
+        .find_map(|(row, l)| l.find('S').map(|column| (row, column)))
+        .ok_or_else(|| anyhow!("No start position found!"))?;
 
-    Err(anyhow!("No start position found!"))
+    let start_tile = infer_start_tile(&field, start_pos)?;
+    field.set(start_pos.0, start_pos.1, start_tile).unwrap();
+
+    Ok((field, start_pos))
 }
 
 #[cfg(test)]
@@ -240,4 +287,19 @@ mod tests {
         let (field, start_pos) = read_input_file("../inputs/day10_example3.txt").unwrap();
         assert_eq!(calc_enclosed_tiles(&field, start_pos), 10);
     }
+
+    #[test]
+    fn start_tile_inference() {
+        let (field, start_pos) = read_input_file("../inputs/day10_example1.txt").unwrap();
+        assert_eq!(start_pos, (2, 0));
+        assert_eq!(field.get(2, 0).unwrap(), &vec![Dir::Down, Dir::Right]);
+
+        let (field, _) = read_input_file("../inputs/day10_example3.txt").unwrap();
+        assert_eq!(field.get(0, 4).unwrap(), &vec![Dir::Down, Dir::Left]);
+
+        // Start tile connected to three neighbors
+        assert!(read_input_file("../inputs/day10_example4.txt").is_err());
+        // Start tile connected to a single neighbor only
+        assert!(read_input_file("../inputs/day10_example5.txt").is_err());
+    }
 }
@@ -1,6 +1,7 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use lazy_static::lazy_static;
 use regex::Regex;
+use std::collections::HashMap;
 use std::fs::read_to_string;
 use std::path::Path;
 
@@ -9,109 +10,256 @@ lazy_static! {
     static ref NUMBERS_REGEX: Regex = Regex::new("\\d+").unwrap();
 }
 
-/// Extracts the sum of all numbers that are adjacent to the given field, if this field contains a symbol.
-fn calc_adjacent_number_sum(f: &[Vec<u8>], row: usize, col: usize) -> Option<u32> {
-    let c = f[row][col];
+type Position = (usize, usize);
 
-    if c as char == '.' || c.is_ascii_digit() {
-        None
-    } else {
-        let mut sum = get_adjacent_numbers_from_row(&f[row], col).iter().sum();
+/// Number in the schematic, spanning the columns col_start..col_end (end exclusive, counted in characters) in a single row.
+#[derive(Debug)]
+struct Number {
+    value: u64,
+    row: usize,
+    col_start: usize,
+    col_end: usize,
+}
+
+/// Any character in the schematic that is neither a digit nor a dot.
+#[derive(Debug)]
+struct Symbol {
+    c: char,
+    pos: Position,
+}
+
+/// Parsed engine schematic with a precomputed adjacency between numbers and symbols (indices into the respective vectors).
+/// A number and a symbol are adjacent if they are at most adjacency_radius fields apart in both directions.
+#[derive(Debug)]
+struct Schematic {
+    numbers: Vec<Number>,
+    symbols: Vec<Symbol>,
+    numbers_by_symbol: Vec<Vec<usize>>,
+    symbols_by_number: Vec<Vec<usize>>,
+}
+
+impl Schematic {
+    fn new(rows: &[&str], adjacency_radius: usize) -> Result<Schematic> {
+        let mut numbers = vec![];
+        let mut symbols = vec![];
+
+        for (row, line) in rows.iter().enumerate() {
+            for m in NUMBERS_REGEX.find_iter(line) {
+                // Regex matches are byte offsets, which differ from the columns if there are non-ASCII symbols before the number
+                let col_start = line[..m.start()].chars().count();
+                numbers.push(Number {
+                    value: m
+                        .as_str()
+                        .parse()
+                        .with_context(|| format!("Could not parse number: {}", m.as_str()))?,
+                    row,
+                    col_start,
+                    col_end: col_start + m.as_str().len(),
+                });
+            }
 
-        if row != 0 {
-            sum += get_adjacent_numbers_from_row(&f[row - 1], col)
-                .iter()
-                .sum::<u32>();
+            for (col, c) in line.chars().enumerate() {
+                if c != '.' && !c.is_ascii_digit() {
+                    symbols.push(Symbol { c, pos: (row, col) });
+                }
+            }
         }
 
-        if row != f.len() - 1 {
-            sum += get_adjacent_numbers_from_row(&f[row + 1], col)
-                .iter()
-                .sum::<u32>();
+        // Build bipartite adjacency by looking at the fields surrounding every number
+        let symbol_index: HashMap<Position, usize> = symbols
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.pos, i))
+            .collect();
+        let mut numbers_by_symbol = vec![vec![]; symbols.len()];
+        let mut symbols_by_number = vec![vec![]; numbers.len()];
+
+        for (number_idx, n) in numbers.iter().enumerate() {
+            let r = adjacency_radius;
+            for row in n.row.saturating_sub(r)..=n.row + r {
+                for col in n.col_start.saturating_sub(r)..n.col_end + r {
+                    if let Some(symbol_idx) = symbol_index.get(&(row, col)) {
+                        numbers_by_symbol[*symbol_idx].push(number_idx);
+                        symbols_by_number[number_idx].push(*symbol_idx);
+                    }
+                }
+            }
         }
 
-        Some(sum)
+        Ok(Schematic {
+            numbers,
+            symbols,
+            numbers_by_symbol,
+            symbols_by_number,
+        })
     }
-}
 
-/// Extracts the product of two adjacent numbers if this field contains a * symbol (second part of the puzzle).
-fn calc_adjacent_gear_ratio(f: &[Vec<u8>], row: usize, col: usize) -> Option<u32> {
-    let c = f[row][col];
+    /// Returns all numbers that are adjacent to the given symbol.
+    fn numbers_adjacent_to_symbol(&self, symbol_idx: usize) -> impl Iterator<Item = &Number> {
+        self.numbers_by_symbol[symbol_idx]
+            .iter()
+            .map(|i| &self.numbers[*i])
+    }
 
-    if c as char == '*' {
-        let mut numbers = vec![];
+    /// Returns all symbols that are adjacent to the given number.
+    fn symbols_adjacent_to_number(&self, number_idx: usize) -> impl Iterator<Item = &Symbol> {
+        self.symbols_by_number[number_idx]
+            .iter()
+            .map(|i| &self.symbols[*i])
+    }
 
-        numbers.append(&mut get_adjacent_numbers_from_row(&f[row], col));
+    /// Checks for numbers that are attributed to more than one symbol, numbers without any symbol and symbols without any number.
+    fn validate(&self) -> ValidationReport<'_> {
+        let mut report = ValidationReport::default();
 
-        if row != 0 {
-            numbers.append(&mut get_adjacent_numbers_from_row(&f[row - 1], col));
+        for (i, n) in self.numbers.iter().enumerate() {
+            match self.symbols_by_number[i].len() {
+                0 => report.orphan_numbers.push(n),
+                1 => {}
+                _ => report
+                    .duplicate_attributions
+                    .push((n, self.symbols_adjacent_to_number(i).collect())),
+            }
         }
 
-        if row != f.len() - 1 {
-            numbers.append(&mut get_adjacent_numbers_from_row(&f[row + 1], col));
+        for (i, sym) in self.symbols.iter().enumerate() {
+            if self.numbers_by_symbol[i].is_empty() {
+                report.isolated_symbols.push(sym);
+            }
         }
 
-        if numbers.len() == 2 {
-            return Some(numbers.first().unwrap() * numbers.get(1).unwrap());
-        }
+        report
     }
+}
 
-    None
+#[derive(Debug, Default)]
+struct ValidationReport<'a> {
+    duplicate_attributions: Vec<(&'a Number, Vec<&'a Symbol>)>,
+    orphan_numbers: Vec<&'a Number>,
+    isolated_symbols: Vec<&'a Symbol>,
 }
 
-fn calc_field_result(f: &[Vec<u8>], compute_gear_ratio: bool) -> u32 {
-    let mut sum = 0;
+/// Sums up all numbers adjacent to a symbol (first part of the puzzle). Without unique attribution, the numbers are summed up per
+/// symbol, so that a number adjacent to multiple symbols is counted multiple times.
+fn calc_adjacent_number_sum(s: &Schematic, unique_attribution: bool) -> Result<u64> {
+    let values: Vec<u64> = if unique_attribution {
+        s.numbers
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !s.symbols_by_number[*i].is_empty())
+            .map(|(_, n)| n.value)
+            .collect()
+    } else {
+        (0..s.symbols.len())
+            .flat_map(|i| s.numbers_adjacent_to_symbol(i).map(|n| n.value))
+            .collect()
+    };
 
-    for row in 0..f.len() {
-        for col in 0..f[row].len() {
-            let res = if compute_gear_ratio {
-                calc_adjacent_gear_ratio(f, row, col)
-            } else {
-                calc_adjacent_number_sum(f, row, col)
-            };
+    values
+        .into_iter()
+        .try_fold(0u64, |sum, v| sum.checked_add(v))
+        .context("Overflow when summing up numbers adjacent to symbols!")
+}
 
-            if let Some(value) = res {
-                sum += value;
-            }
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+enum NeighborCount {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl NeighborCount {
+    fn matches(&self, count: usize) -> bool {
+        match self {
+            NeighborCount::Exactly(n) => count == *n,
+            NeighborCount::AtLeast(n) => count >= *n,
         }
     }
+}
 
-    sum
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+enum GearCombination {
+    Product,
+    Sum,
+    Max,
 }
 
-/// Returns every number in the row that is adjacent to the given column index
-fn get_adjacent_numbers_from_row(row: &[u8], col_index: usize) -> Vec<u32> {
-    let mut numbers = vec![];
+/// Defines which symbols are gears and how the numbers adjacent to them are combined into a gear ratio.
+#[derive(Debug)]
+struct GearRules {
+    gear_symbols: Vec<char>,
+    neighbor_count: NeighborCount,
+    combination: GearCombination,
+}
 
-    for m in NUMBERS_REGEX.find_iter(&String::from_utf8(row.to_owned()).unwrap()) {
-        if m.start() <= col_index + 1 && m.end() >= col_index {
-            numbers.push(m.as_str().parse().unwrap());
+impl Default for GearRules {
+    /// Rules from the second part of the puzzle: a * with exactly two adjacent numbers, which are multiplied.
+    fn default() -> Self {
+        GearRules {
+            gear_symbols: vec!['*'],
+            neighbor_count: NeighborCount::Exactly(2),
+            combination: GearCombination::Product,
         }
     }
+}
+
+/// Sums up the gear ratios of all symbols that are gears according to the given rules.
+fn calc_gear_ratio_sum(s: &Schematic, rules: &GearRules) -> Result<u64> {
+    s.symbols
+        .iter()
+        .enumerate()
+        .filter(|(i, sym)| {
+            rules.gear_symbols.contains(&sym.c)
+                && rules.neighbor_count.matches(s.numbers_by_symbol[*i].len())
+        })
+        .try_fold(0u64, |sum, (i, sym)| {
+            let mut values = s.numbers_adjacent_to_symbol(i).map(|n| n.value);
+            let ratio = match rules.combination {
+                GearCombination::Product => values.try_fold(1u64, |p, v| p.checked_mul(v)),
+                GearCombination::Sum => values.try_fold(0u64, |p, v| p.checked_add(v)),
+                GearCombination::Max => Some(values.max().unwrap_or(0)),
+            }
+            .with_context(|| format!("Overflow in gear ratio of symbol at {:?}!", sym.pos))?;
 
-    numbers
+            sum.checked_add(ratio)
+                .context("Overflow when summing up gear ratios!")
+        })
 }
 
 fn main() -> Result<()> {
-    let field = read_input_file("../inputs/day3_input.txt")?;
+    let schematic = read_input_file("../inputs/day3_input.txt", 1)?;
 
     println!(
         "Sum of all numbers that adjacent to symbols: {}",
-        calc_field_result(&field, false)
+        calc_adjacent_number_sum(&schematic, false)?
+    );
+
+    println!(
+        "Sum of all numbers that adjacent to symbols (counting each number once): {}",
+        calc_adjacent_number_sum(&schematic, true)?
     );
 
     println!(
         "Sum of all gear ratios: {}",
-        calc_field_result(&field, true)
+        calc_gear_ratio_sum(&schematic, &GearRules::default())?
+    );
+
+    let report = schematic.validate();
+    println!(
+        "Numbers adjacent to multiple symbols: {}, numbers without symbol: {}, symbols without number: {}",
+        report.duplicate_attributions.len(),
+        report.orphan_numbers.len(),
+        report.isolated_symbols.len()
     );
 
     Ok(())
 }
 
-fn read_input_file<P: AsRef<Path>>(input_path: P) -> Result<Vec<Vec<u8>>> {
+fn read_input_file<P: AsRef<Path>>(input_path: P, adjacency_radius: usize) -> Result<Schematic> {
     let input = read_to_string(input_path)?;
-    let rows: Vec<_> = input.lines().map(|l| l.as_bytes().to_vec()).collect();
-    Ok(rows)
+    let rows: Vec<_> = input.lines().collect();
+    Schematic::new(&rows, adjacency_radius)
 }
 
 #[cfg(test)]
@@ -120,13 +268,98 @@ mod tests {
 
     #[test]
     fn example_first_star() {
-        let field = read_input_file("../inputs/day3_example.txt").unwrap();
-        assert_eq!(calc_field_result(&field, false), 4361);
+        let schematic = read_input_file("../inputs/day3_example.txt", 1).unwrap();
+        assert_eq!(calc_adjacent_number_sum(&schematic, false).unwrap(), 4361);
     }
 
     #[test]
     fn example_second_star() {
-        let field = read_input_file("../inputs/day3_example.txt").unwrap();
-        assert_eq!(calc_field_result(&field, true), 467835);
+        let schematic = read_input_file("../inputs/day3_example.txt", 1).unwrap();
+        assert_eq!(
+            calc_gear_ratio_sum(&schematic, &GearRules::default()).unwrap(),
+            467835
+        );
+    }
+
+    #[test]
+    fn example_custom_gear_rules() {
+        let schematic = read_input_file("../inputs/day3_example.txt", 1).unwrap();
+        let rules = GearRules {
+            gear_symbols: vec!['*', '#'],
+            neighbor_count: NeighborCount::AtLeast(1),
+            combination: GearCombination::Max,
+        };
+
+        // * symbols: max(467, 35) + 617 + max(755, 598), # symbol: 633
+        assert_eq!(
+            calc_gear_ratio_sum(&schematic, &rules).unwrap(),
+            467 + 617 + 755 + 633
+        );
+
+        // Product of six five-digit numbers does not fit into an u64
+        let schematic = Schematic::new(&["99999.99999", "99999*99999", "99999.99999"], 1).unwrap();
+        let rules = GearRules {
+            neighbor_count: NeighborCount::AtLeast(2),
+            ..GearRules::default()
+        };
+        assert!(calc_gear_ratio_sum(&schematic, &rules).is_err());
+    }
+
+    #[test]
+    fn example_validation() {
+        let schematic = read_input_file("../inputs/day3_example.txt", 1).unwrap();
+        let report = schematic.validate();
+
+        assert!(report.duplicate_attributions.is_empty());
+        let orphans: Vec<_> = report
+            .orphan_numbers
+            .iter()
+            .map(|n| (n.value, n.row, n.col_start))
+            .collect();
+        assert_eq!(orphans, vec![(114, 0, 5), (58, 5, 7)]);
+        assert!(report.isolated_symbols.is_empty());
+
+        // 35 touches both the * and the # when using a radius of two
+        let schematic = read_input_file("../inputs/day3_example.txt", 2).unwrap();
+        let report = schematic.validate();
+        assert!(report
+            .duplicate_attributions
+            .iter()
+            .any(|(n, symbols)| n.value == 35 && symbols.len() == 2));
+        assert!(
+            calc_adjacent_number_sum(&schematic, true).unwrap()
+                < calc_adjacent_number_sum(&schematic, false).unwrap()
+        );
+    }
+
+    #[test]
+    fn example_adjacency() {
+        let schematic = read_input_file("../inputs/day3_example.txt", 1).unwrap();
+
+        // 467 in the top left corner only touches the * in the second row
+        let symbols: Vec<_> = schematic.symbols_adjacent_to_number(0).collect();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols[0].c, '*');
+        assert_eq!(symbols[0].pos, (1, 3));
+
+        // 114 touches no symbol at all
+        assert_eq!(schematic.symbols_adjacent_to_number(1).count(), 0);
+
+        // With a radius of two, 114 reaches the * in the second row
+        let schematic = read_input_file("../inputs/day3_example.txt", 2).unwrap();
+        assert_eq!(schematic.symbols_adjacent_to_number(1).count(), 1);
+
+        // Non-ASCII symbols take up a single column
+        let schematic = Schematic::new(&["€12..", "..€.5"], 1).unwrap();
+        assert_eq!(schematic.symbols[1].pos, (1, 2));
+        assert_eq!(schematic.numbers[1].col_start, 4);
+        assert_eq!(schematic.symbols_adjacent_to_number(0).count(), 2);
+        assert_eq!(schematic.symbols_adjacent_to_number(1).count(), 0);
+
+        // Numbers beyond u32 are fine, while numbers beyond u64 and sums beyond u64 are reported
+        let schematic = Schematic::new(&["12345678901#", "#9999999999999999999"], 1).unwrap();
+        assert_eq!(schematic.numbers[0].value, 12345678901);
+        assert!(calc_adjacent_number_sum(&schematic, false).is_err());
+        assert!(Schematic::new(&["99999999999999999999#"], 1).is_err());
     }
 }
@@ -1,49 +1,228 @@
-use anyhow::Result;
+use anyhow::{ensure, Context, Result};
 use regex::Regex;
 use std::collections::HashSet;
-use std::fs::read_to_string;
+use std::fs::{create_dir_all, read_to_string, write};
 use std::path::Path;
 
 #[derive(Debug)]
 struct ScratchCard {
-    winning_numbers: Vec<u8>,
-    numbers_we_have: Vec<u8>,
+    winning_numbers: Vec<u64>,
+    numbers_we_have: Vec<u64>,
 }
 
-fn calc_points(sc: &ScratchCard) -> u32 {
-    let win_count = calc_win_count(sc);
+/// Determines how many points a card with a given number of winning numbers is worth.
+#[allow(dead_code)]
+#[derive(Debug)]
+enum ScoringRule {
+    /// Fixed amount of points per winning number.
+    Linear(u128),
+    /// Point for the first winning number, multiplied by the base for every further winning number.
+    Exponential(u128),
+    /// Points indexed by the number of winning numbers.
+    Lookup(Vec<u128>),
+}
+
+impl Default for ScoringRule {
+    /// Rule from the first part of the puzzle: one point that is doubled for every further winning number.
+    fn default() -> Self {
+        ScoringRule::Exponential(2)
+    }
+}
+
+impl ScoringRule {
+    fn score(&self, win_count: usize) -> Result<u128> {
+        let score = match self {
+            ScoringRule::Linear(points) => points.checked_mul(win_count as u128),
+            ScoringRule::Exponential(base) => {
+                if win_count > 0 {
+                    u32::try_from(win_count - 1)
+                        .ok()
+                        .and_then(|exp| base.checked_pow(exp))
+                } else {
+                    Some(0)
+                }
+            }
+            ScoringRule::Lookup(table) => {
+                return table.get(win_count).cloned().with_context(|| {
+                    format!("No points defined for {} winning numbers!", win_count)
+                })
+            }
+        };
+
+        score.with_context(|| {
+            format!(
+                "Overflow when calculating points for {} winning numbers!",
+                win_count
+            )
+        })
+    }
+}
+
+/// Determines which cards a card with a given number of winning numbers wins a copy of.
+#[allow(dead_code)]
+#[derive(Debug)]
+enum CopyRule {
+    /// One copy of each of the next N cards, stopping at the end of the deck.
+    NextCards,
+    /// One copy of every k-th card after this one, N times, stopping at the end of the deck (k has to be positive).
+    EveryKthCard(usize),
+    /// One copy of each of the next N cards, continuing at the start of the deck after the last card.
+    WrapAround,
+}
+
+impl Default for CopyRule {
+    /// Rule from the second part of the puzzle.
+    fn default() -> Self {
+        CopyRule::NextCards
+    }
+}
+
+impl CopyRule {
+    /// Returns the indices of all cards won by the card with the given index.
+    fn targets(&self, card: usize, win_count: usize, deck_size: usize) -> Vec<usize> {
+        match self {
+            CopyRule::NextCards => (card + 1..=card + win_count)
+                .filter(|j| *j < deck_size)
+                .collect(),
+            CopyRule::EveryKthCard(k) => (1..=win_count)
+                .map(|m| card + m * k)
+                .filter(|j| *j < deck_size)
+                .collect(),
+            CopyRule::WrapAround => (1..=win_count).map(|m| (card + m) % deck_size).collect(),
+        }
+    }
+}
+
+fn calc_points(sc: &ScratchCard, rule: &ScoringRule) -> Result<u128> {
+    rule.score(calc_win_count(sc))
+}
 
-    if win_count > 0 {
-        u32::pow(2, win_count - 1)
-    } else {
-        0
+fn calc_total_points(all_scs: &[ScratchCard], rule: &ScoringRule) -> Result<u128> {
+    all_scs.iter().try_fold(0u128, |sum, sc| {
+        sum.checked_add(calc_points(sc, rule)?)
+            .context("Overflow when summing up points!")
+    })
+}
+
+/// Origin of all copies of a single card: the original card plus the copies won from each other card.
+#[derive(Debug)]
+struct CardProvenance {
+    original: u128,
+    won_from: Vec<(usize, u128)>,
+}
+
+impl CardProvenance {
+    fn total(&self) -> Result<u128> {
+        self.won_from
+            .iter()
+            .try_fold(self.original, |sum, (_, count)| sum.checked_add(*count))
+            .context("Overflow when summing up copies of a card!")
     }
 }
 
-fn calc_total_card_count(all_scs: &[ScratchCard]) -> u32 {
+/// Plays the cascade of the second part of the puzzle and records for every card (by index) where its copies came from. Cards are
+/// played in order, so copies of earlier cards (only possible when wrapping around) are counted but do not win further cards.
+fn calc_card_cascade(all_scs: &[ScratchCard], rule: &CopyRule) -> Result<Vec<CardProvenance>> {
+    ensure!(
+        !matches!(rule, CopyRule::EveryKthCard(0)),
+        "Copying every 0th card would make cards win copies of themselves!"
+    );
+
     let n = all_scs.len();
-    let mut card_count = vec![1; n];
+    let mut cascade: Vec<_> = (0..n)
+        .map(|_| CardProvenance {
+            original: 1,
+            won_from: vec![],
+        })
+        .collect();
 
     for i in 0..n {
-        let multiplier = card_count[i];
+        let multiplier = cascade[i]
+            .total()
+            .with_context(|| format!("Overflow in number of copies of card {}!", i + 1))?;
         let win_count = calc_win_count(&all_scs[i]);
 
-        let mut j = i + 1;
-        while j < n && j <= i + win_count as usize {
-            card_count[j] += multiplier;
-            j += 1;
+        for j in rule.targets(i, win_count, n) {
+            cascade[j].won_from.push((i, multiplier));
+        }
+    }
+
+    Ok(cascade)
+}
+
+fn calc_total_card_count(all_scs: &[ScratchCard], rule: &CopyRule) -> Result<u128> {
+    calc_card_cascade(all_scs, rule)?
+        .iter()
+        .try_fold(0u128, |sum, cp| {
+            sum.checked_add(cp.total()?)
+                .context("Overflow when summing up card counts!")
+        })
+}
+
+/// Sums up the copies that each card won for other cards, which shows which cards dominate the cascade.
+fn calc_copies_won_per_card(cascade: &[CardProvenance]) -> Result<Vec<u128>> {
+    let mut won = vec![0u128; cascade.len()];
+
+    for cp in cascade {
+        for (from, count) in &cp.won_from {
+            won[*from] = won[*from].checked_add(*count).with_context(|| {
+                format!("Overflow in number of copies won by card {}!", from + 1)
+            })?;
         }
     }
 
-    card_count.iter().sum()
+    Ok(won)
+}
+
+/// Renders the cascade as a table with one line per card.
+fn format_cascade_table(cascade: &[CardProvenance]) -> Result<String> {
+    let won = calc_copies_won_per_card(cascade)?;
+    let mut s = String::from("Card | Total | Original | Won from\n");
+
+    for (i, cp) in cascade.iter().enumerate() {
+        let won_from: Vec<_> = cp
+            .won_from
+            .iter()
+            .map(|(from, count)| format!("{}x card {}", count, from + 1))
+            .collect();
+        s.push_str(&format!(
+            "{} | {} | {} | {} (won {} copies for other cards)\n",
+            i + 1,
+            cp.total()?,
+            cp.original,
+            won_from.join(", "),
+            won[i]
+        ));
+    }
+
+    Ok(s)
 }
 
-fn calc_win_count(sc: &ScratchCard) -> u32 {
-    let winning_set: HashSet<u8> = sc.winning_numbers.iter().cloned().collect();
+/// Renders the cascade as a Graphviz DOT graph with an edge for every card that won copies of another card.
+fn format_cascade_dot(cascade: &[CardProvenance]) -> String {
+    let mut s = String::from("digraph cascade {\n");
+
+    for (i, cp) in cascade.iter().enumerate() {
+        for (from, count) in &cp.won_from {
+            s.push_str(&format!(
+                "    card{} -> card{} [label=\"{}\"];\n",
+                from + 1,
+                i + 1,
+                count
+            ));
+        }
+    }
+
+    s.push_str("}\n");
+    s
+}
+
+fn calc_win_count(sc: &ScratchCard) -> usize {
+    let winning_set: HashSet<u64> = sc.winning_numbers.iter().cloned().collect();
     sc.numbers_we_have
         .iter()
         .filter(|n| winning_set.contains(n))
-        .count() as u32
+        .count()
 }
 
 fn main() -> Result<()> {
@@ -51,46 +230,62 @@ fn main() -> Result<()> {
 
     println!(
         "Sum of all points (first star): {}",
-        input.iter().map(calc_points).sum::<u32>()
+        calc_total_points(&input, &ScoringRule::default())?
     );
 
     println!(
         "Total number of cards (second star): {}",
-        calc_total_card_count(&input)
+        calc_total_card_count(&input, &CopyRule::default())?
     );
 
+    let cascade = calc_card_cascade(&input, &CopyRule::default())?;
+    let won = calc_copies_won_per_card(&cascade)?;
+    if let Some((i, count)) = won.iter().enumerate().max_by_key(|(_, count)| **count) {
+        println!(
+            "Card that won the most copies: {} ({} copies)",
+            i + 1,
+            count
+        );
+    }
+
+    create_dir_all("../outputs")?;
+    write(
+        "../outputs/day4_cascade.txt",
+        format_cascade_table(&cascade)?,
+    )?;
+    write("../outputs/day4_cascade.dot", format_cascade_dot(&cascade))?;
+    println!("Cascade written to ../outputs/day4_cascade.txt and ../outputs/day4_cascade.dot");
+
     Ok(())
 }
 
+fn parse_numbers(s: &str) -> Result<Vec<u64>> {
+    s.split_whitespace()
+        .map(|num_s| {
+            num_s
+                .parse()
+                .with_context(|| format!("Could not parse number: {}", num_s))
+        })
+        .collect()
+}
+
 fn read_input_file<P: AsRef<Path>>(input_path: P) -> Result<Vec<ScratchCard>> {
     let r = Regex::new(r"^Card +\d+: ([\d ]+) \| ([\d ]+)$").unwrap();
 
     let input = read_to_string(input_path)?;
-    let parsed: Vec<_> = input
+    input
         .lines()
         .map(|l| {
-            let cap = r.captures(l).expect("Could not parse line!");
-
-            ScratchCard {
-                winning_numbers: cap
-                    .get(1)
-                    .unwrap()
-                    .as_str()
-                    .split_whitespace()
-                    .map(|num_s| num_s.parse().unwrap())
-                    .collect(),
-                numbers_we_have: cap
-                    .get(2)
-                    .unwrap()
-                    .as_str()
-                    .split_whitespace()
-                    .map(|num_s| num_s.parse().unwrap())
-                    .collect(),
-            }
-        })
-        .collect();
+            let cap = r
+                .captures(l)
+                .with_context(|| format!("Could not parse line: {}", l))?;
 
-    Ok(parsed)
+            Ok(ScratchCard {
+                winning_numbers: parse_numbers(cap.get(1).unwrap().as_str())?,
+                numbers_we_have: parse_numbers(cap.get(2).unwrap().as_str())?,
+            })
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -100,12 +295,114 @@ mod tests {
     #[test]
     fn example_first_star() {
         let input = read_input_file("../inputs/day4_example.txt").unwrap();
-        assert_eq!(input.iter().map(calc_points).sum::<u32>(), 13);
+        assert_eq!(
+            calc_total_points(&input, &ScoringRule::default()).unwrap(),
+            13
+        );
     }
 
     #[test]
     fn example_second_star() {
         let input = read_input_file("../inputs/day4_example.txt").unwrap();
-        assert_eq!(calc_total_card_count(&input), 30);
+        assert_eq!(
+            calc_total_card_count(&input, &CopyRule::default()).unwrap(),
+            30
+        );
+    }
+
+    #[test]
+    fn example_custom_rules() {
+        let input = read_input_file("../inputs/day4_example.txt").unwrap();
+
+        // Winning numbers per card: 4, 2, 2, 1, 0, 0
+        assert_eq!(
+            calc_total_points(&input, &ScoringRule::Linear(3)).unwrap(),
+            27
+        );
+        assert_eq!(
+            calc_total_points(&input, &ScoringRule::Exponential(3)).unwrap(),
+            27 + 3 + 3 + 1
+        );
+        assert_eq!(
+            calc_total_points(&input, &ScoringRule::Lookup(vec![0, 1, 5, 10, 20])).unwrap(),
+            20 + 5 + 5 + 1
+        );
+        assert!(calc_total_points(&input, &ScoringRule::Lookup(vec![0, 1])).is_err());
+
+        // Card 1 wins cards 3 and 5, card 2 wins cards 4 and 6, the two copies of card 3 win card 5 and the two copies of card 4 win
+        // card 6, resulting in 1, 1, 2, 2, 4 and 4 copies
+        assert_eq!(
+            calc_total_card_count(&input, &CopyRule::EveryKthCard(2)).unwrap(),
+            14
+        );
+
+        assert!(calc_total_card_count(&input, &CopyRule::EveryKthCard(0)).is_err());
+
+        // Wrapping around only changes the result if a card near the end of the deck wins something
+        assert_eq!(
+            calc_total_card_count(&input, &CopyRule::WrapAround).unwrap(),
+            30
+        );
+        assert_eq!(CopyRule::WrapAround.targets(4, 3, 6), vec![5, 0, 1]);
+
+        // Card 1 wins card 2, and card 3 wins cards 1 and 2 only when wrapping around
+        let input = read_input_file("../inputs/day4_example_wrap.txt").unwrap();
+        assert_eq!(
+            calc_total_card_count(&input, &CopyRule::NextCards).unwrap(),
+            4
+        );
+        assert_eq!(
+            calc_total_card_count(&input, &CopyRule::WrapAround).unwrap(),
+            6
+        );
+    }
+
+    #[test]
+    fn example_cascade() {
+        let input = read_input_file("../inputs/day4_example.txt").unwrap();
+        let cascade = calc_card_cascade(&input, &CopyRule::default()).unwrap();
+
+        // Card 4 has one original and gets one copy from card 1, two copies from card 2 and four copies from card 3
+        assert_eq!(cascade[3].original, 1);
+        assert_eq!(cascade[3].won_from, vec![(0, 1), (1, 2), (2, 4)]);
+        assert_eq!(cascade[3].total().unwrap(), 8);
+
+        assert_eq!(
+            calc_copies_won_per_card(&cascade).unwrap(),
+            vec![4, 4, 8, 8, 0, 0]
+        );
+        assert!(format_cascade_dot(&cascade).contains("card3 -> card4 [label=\"4\"];"));
+        assert!(format_cascade_table(&cascade)
+            .unwrap()
+            .contains("4 | 8 | 1 | 1x card 1, 2x card 2, 4x card 3"));
+    }
+
+    #[test]
+    fn overflow_is_reported() {
+        // 200 matching numbers are worth 2^199 points, which does not fit into an u128
+        let numbers: Vec<u64> = (1_000_000..1_000_200).collect();
+        let sc = ScratchCard {
+            winning_numbers: numbers.clone(),
+            numbers_we_have: numbers,
+        };
+        assert!(calc_points(&sc, &ScoringRule::default()).is_err());
+
+        // Every card winning a copy of all following cards doubles the count per card, which also overflows eventually
+        let all_scs: Vec<_> = (0..200)
+            .map(|_| ScratchCard {
+                winning_numbers: (0..200).collect(),
+                numbers_we_have: (0..200).collect(),
+            })
+            .collect();
+        assert!(calc_total_card_count(&all_scs, &CopyRule::default()).is_err());
+
+        // Copies won by a single card can overflow even if the copies of every card still fit
+        let cascade: Vec<_> = (0..3)
+            .map(|i| CardProvenance {
+                original: 1,
+                won_from: if i == 0 { vec![] } else { vec![(0, u128::MAX)] },
+            })
+            .collect();
+        assert!(calc_copies_won_per_card(&cascade).is_err());
     }
 }
@@ -1,11 +1,100 @@
-use anyhow::Result;
+use anyhow::{anyhow, ensure, Context, Result};
 use itertools::Itertools;
 use regex::Regex;
+use std::fmt;
 use std::fs::read_to_string;
 use std::path::Path;
+
+/// Half-open range of values (start, end).
+type Range = (u64, u64);
+
 struct Input {
     seeds: Vec<u64>,
-    mappings: Vec<Vec<MappingRule>>,
+    mappings: Vec<MappingTable>,
+}
+
+/// Mapping table from the source category to the destination category (e.g., "seed" to "soil").
+struct MappingTable {
+    source: String,
+    destination: String,
+    rules: Vec<MappingRule>,
+}
+
+/// Linear piece of the composed mapping that maps the source range to a range of the same length starting at destination_start.
+#[derive(Debug, Eq, PartialEq)]
+struct Segment {
+    source: Range,
+    destination_start: u64,
+}
+
+/// All mapping tables composed into a single sorted list of non-overlapping segments covering every value below u64::MAX. The value
+/// u64::MAX itself cannot be covered by a rule that does not overflow, so it always maps to itself.
+struct ComposedMapping {
+    segments: Vec<Segment>,
+}
+
+impl ComposedMapping {
+    fn new(input: &Input) -> ComposedMapping {
+        let mut segments = vec![Segment {
+            source: (0, u64::MAX),
+            destination_start: 0,
+        }];
+
+        for mapping_table in &input.mappings {
+            segments = segments
+                .into_iter()
+                .flat_map(|seg| {
+                    let len = seg.source.1 - seg.source.0;
+                    split_range_through_table(
+                        &mapping_table.rules,
+                        (seg.destination_start, seg.destination_start + len),
+                    )
+                    .into_iter()
+                    .map(move |((start, end), mapped_start)| {
+                        let source_start = seg.source.0 + (start - seg.destination_start);
+                        Segment {
+                            source: (source_start, source_start + (end - start)),
+                            destination_start: mapped_start,
+                        }
+                    })
+                })
+                .collect();
+        }
+
+        // Sort and merge neighboring segments that continue each other, so that only real discontinuities remain
+        segments.sort_unstable_by_key(|seg| seg.source.0);
+        let mut merged: Vec<Segment> = vec![];
+
+        for seg in segments {
+            if let Some(last) = merged.last_mut() {
+                if last.source.1 == seg.source.0
+                    && last.destination_start + (last.source.1 - last.source.0)
+                        == seg.destination_start
+                {
+                    last.source.1 = seg.source.1;
+                    continue;
+                }
+            }
+
+            merged.push(seg);
+        }
+
+        ComposedMapping { segments: merged }
+    }
+
+    fn evaluate(&self, seed: u64) -> u64 {
+        if seed == u64::MAX {
+            return seed;
+        }
+
+        let idx = self.segments.partition_point(|seg| seg.source.1 <= seed);
+        let seg = &self.segments[idx];
+        seg.destination_start + (seed - seg.source.0)
+    }
+
+    fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
 }
 
 struct MappingRule {
@@ -14,7 +103,8 @@ struct MappingRule {
     range_length: u64,
 }
 
-/// Stupid brute force solution, to be (maybe) optimized later.
+/// Stupid brute force solution, kept as a reference for map_all_seed_ranges().
+#[allow(dead_code)]
 fn brute_force_map_all_seed_ranges(input: &Input) -> u64 {
     input
         .seeds
@@ -31,46 +121,418 @@ fn brute_force_map_all_seed_ranges(input: &Input) -> u64 {
         .unwrap()
 }
 
+/// Maps all seed ranges by pushing whole intervals through the mapping tables and returns the lowest location.
+fn map_all_seed_ranges(input: &Input) -> Result<u64> {
+    map_seed_ranges(input, get_seed_ranges(input)?)
+        .into_iter()
+        .map(|(start, _)| start)
+        .min()
+        .context("No seed ranges given!")
+}
+
+/// Interprets the seeds as pairs of start and length.
+fn get_seed_ranges(input: &Input) -> Result<Vec<Range>> {
+    ensure!(
+        input.seeds.len().is_multiple_of(2),
+        "Odd number of seeds cannot be read as pairs of start and length!"
+    );
+
+    input
+        .seeds
+        .chunks(2)
+        .map(|c| {
+            let end = c[0]
+                .checked_add(c[1])
+                .with_context(|| format!("Seed range starting at {} overflows!", c[0]))?;
+            Ok((c[0], end))
+        })
+        .filter(|r| !matches!(r, Ok((start, end)) if start >= end))
+        .collect()
+}
+
 fn main() -> Result<()> {
     let input = read_input_file("../inputs/day5_input.txt")?;
 
+    for issue in validate_almanac(&input) {
+        println!("Warning: {}", issue);
+    }
+    let composed = ComposedMapping::new(&input);
+
     println!(
         "Lowest location numbers for any seed (first star): {}",
         input
             .seeds
             .iter()
-            .map(|s| map_seed(&input, *s))
+            .map(|s| composed.evaluate(*s))
             .min()
             .unwrap()
     );
 
     println!(
         "Lowest location numbers for all seed ranges (second star): {}",
-        brute_force_map_all_seed_ranges(&input)
+        map_all_seed_ranges(&input)?
+    );
+
+    println!(
+        "Lowest location numbers for all seed ranges using the inverse mapping: {}",
+        find_lowest_location_by_inverse(&input, &get_seed_ranges(&input)?)
+            .context("No location is reachable from the seed ranges!")?
+    );
+
+    println!(
+        "Number of linear segments in the composed seed-to-location mapping: {}",
+        composed.segments().len()
     );
 
+    if let Some(seed) = input.seeds.first() {
+        let soil = map_value_between(&input, "seed", "soil", *seed)?;
+        println!(
+            "First seed {} needs soil {}, which needs humidity {}",
+            seed,
+            soil,
+            map_value_between(&input, "soil", "humidity", soil)?
+        );
+    }
+
     Ok(())
 }
 
+/// Splits a range at the rule boundaries of a single mapping table and returns every piece together with the value its start is
+/// mapped to.
+fn split_range_through_table(table: &[MappingRule], range: Range) -> Vec<(Range, u64)> {
+    let mut mapped = vec![];
+    let mut unmapped = vec![range];
+
+    for mapping_rule in table {
+        let rule_start = mapping_rule.source_start;
+        let rule_end = mapping_rule.source_start + mapping_rule.range_length;
+        let mut remaining = vec![];
+
+        for (start, end) in unmapped {
+            // Part before the rule's source range stays unmapped
+            if start < rule_start.min(end) {
+                remaining.push((start, rule_start.min(end)));
+            }
+
+            // Part overlapping the rule's source range is shifted
+            let overlap_start = start.max(rule_start);
+            let overlap_end = end.min(rule_end);
+            if overlap_start < overlap_end {
+                mapped.push((
+                    (overlap_start, overlap_end),
+                    mapping_rule.destination_start + (overlap_start - rule_start),
+                ));
+            }
+
+            // Part after the rule's source range stays unmapped
+            if rule_end.max(start) < end {
+                remaining.push((rule_end.max(start), end));
+            }
+        }
+
+        unmapped = remaining;
+    }
+
+    // Values not covered by any rule are mapped to themselves
+    mapped.extend(unmapped.into_iter().map(|r| (r, r.0)));
+    mapped
+}
+
+/// Maps a set of ranges through a single mapping table, splitting them at the rule boundaries.
+fn map_ranges_through_table(table: &[MappingRule], ranges: Vec<Range>) -> Vec<Range> {
+    ranges
+        .into_iter()
+        .flat_map(|r| split_range_through_table(table, r))
+        .map(|((start, end), mapped_start)| (mapped_start, mapped_start + (end - start)))
+        .collect()
+}
+
+fn map_seed_ranges(input: &Input, ranges: Vec<Range>) -> Vec<Range> {
+    input.mappings.iter().fold(ranges, |ranges, mapping_table| {
+        map_ranges_through_table(&mapping_table.rules, ranges)
+    })
+}
+
+/// Removes the values of the range to cut from all given ranges.
+fn subtract_range(ranges: Vec<Range>, cut: Range) -> Vec<Range> {
+    let mut result = vec![];
+
+    for (start, end) in ranges {
+        if start < cut.0.min(end) {
+            result.push((start, cut.0.min(end)));
+        }
+        if cut.1.max(start) < end {
+            result.push((cut.1.max(start), end));
+        }
+    }
+
+    result
+}
+
+/// Sorts the ranges and merges overlapping or touching ones.
+fn normalize_ranges(mut ranges: Vec<Range>) -> Vec<Range> {
+    ranges.sort_unstable();
+    let mut merged: Vec<Range> = vec![];
+
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+/// Returns all source ranges of a single mapping table that are mapped into the given destination range.
+fn unmap_range_through_table(table: &[MappingRule], range: Range) -> Vec<Range> {
+    let mut result = vec![];
+
+    for (i, mapping_rule) in table.iter().enumerate() {
+        let rule_start = mapping_rule.destination_start;
+        let rule_end = mapping_rule.destination_start + mapping_rule.range_length;
+        let overlap_start = range.0.max(rule_start);
+        let overlap_end = range.1.min(rule_end);
+
+        if overlap_start < overlap_end {
+            let mut pieces = vec![(
+                mapping_rule.source_start + (overlap_start - rule_start),
+                mapping_rule.source_start + (overlap_end - rule_start),
+            )];
+
+            // Values matched by an earlier rule are not mapped by this rule
+            for earlier_rule in &table[..i] {
+                pieces = subtract_range(
+                    pieces,
+                    (
+                        earlier_rule.source_start,
+                        earlier_rule.source_start + earlier_rule.range_length,
+                    ),
+                );
+            }
+
+            result.extend(pieces);
+        }
+    }
+
+    // Values in the destination range that are not matched by any rule map to themselves
+    let mut identity = vec![range];
+    for mapping_rule in table {
+        identity = subtract_range(
+            identity,
+            (
+                mapping_rule.source_start,
+                mapping_rule.source_start + mapping_rule.range_length,
+            ),
+        );
+    }
+    result.extend(identity);
+
+    result
+}
+
+/// Walks the mapping tables backwards and returns all seed ranges that map into the given location ranges.
+fn unmap_location_ranges(input: &Input, ranges: Vec<Range>) -> Vec<Range> {
+    let seed_ranges = input
+        .mappings
+        .iter()
+        .rev()
+        .fold(ranges, |ranges, mapping_table| {
+            ranges
+                .into_iter()
+                .flat_map(|r| unmap_range_through_table(&mapping_table.rules, r))
+                .collect()
+        });
+
+    normalize_ranges(seed_ranges)
+}
+
+/// Finds the lowest location reachable from the given seed ranges by bisecting the location space and checking whether the seeds
+/// mapping into each half intersect with the given seeds.
+fn find_lowest_location_by_inverse(input: &Input, seed_ranges: &[Range]) -> Option<u64> {
+    let reachable = |locations: Range| {
+        unmap_location_ranges(input, vec![locations])
+            .iter()
+            .any(|(start, end)| {
+                seed_ranges
+                    .iter()
+                    .any(|(seed_start, seed_end)| start < seed_end && seed_start < end)
+            })
+    };
+
+    let mut locations = (0, u64::MAX);
+    if !reachable(locations) {
+        return None;
+    }
+
+    while locations.1 - locations.0 > 1 {
+        let mid = locations.0 + (locations.1 - locations.0) / 2;
+        locations = if reachable((locations.0, mid)) {
+            (locations.0, mid)
+        } else {
+            (mid, locations.1)
+        };
+    }
+
+    Some(locations.0)
+}
+
 fn map_seed(input: &Input, seed: u64) -> u64 {
-    let mut value = seed;
+    input.mappings.iter().fold(seed, |value, mapping_table| {
+        map_value_through_table(&mapping_table.rules, value)
+    })
+}
+
+fn map_value_through_table(table: &[MappingRule], value: u64) -> u64 {
+    for mapping_rule in table {
+        if value >= mapping_rule.source_start
+            && value < mapping_rule.source_start + mapping_rule.range_length
+        {
+            return mapping_rule.destination_start + (value - mapping_rule.source_start);
+        }
+    }
+
+    value
+}
+
+/// Maps a value from one category to another (e.g., "soil" to "humidity") by following the tables' category names.
+fn map_value_between(input: &Input, from: &str, to: &str, value: u64) -> Result<u64> {
+    Ok(find_table_chain(input, from, to)?
+        .into_iter()
+        .fold(value, |value, mapping_table| {
+            map_value_through_table(&mapping_table.rules, value)
+        }))
+}
+
+/// Returns the tables that have to be applied in order to get from one category to another.
+fn find_table_chain<'a>(input: &'a Input, from: &str, to: &str) -> Result<Vec<&'a MappingTable>> {
+    let mut chain = vec![];
+    let mut category = from;
+
+    while category != to {
+        let mapping_table = input
+            .mappings
+            .iter()
+            .find(|t| t.source == category)
+            .with_context(|| format!("No mapping table from category {}!", category))?;
+
+        if chain.len() == input.mappings.len() {
+            return Err(anyhow!(
+                "Cycle in mapping tables starting at category {}!",
+                from
+            ));
+        }
 
-    for mapping_table in &input.mappings {
-        for mapping_rule in mapping_table {
-            if value >= mapping_rule.source_start
-                && value < mapping_rule.source_start + mapping_rule.range_length
+        chain.push(mapping_table);
+        category = &mapping_table.destination;
+    }
+
+    Ok(chain)
+}
+
+#[derive(Debug, PartialEq)]
+enum AlmanacIssue {
+    /// Destination of a table does not match the source of the next table.
+    BrokenChain { table: usize },
+    /// Two rules of the same table have overlapping source ranges.
+    OverlappingSources {
+        table: usize,
+        rule1: usize,
+        rule2: usize,
+    },
+    /// Source or destination range of a rule does not fit into an u64.
+    Overflow { table: usize, rule: usize },
+    /// Seeds cannot be read as pairs of start and length.
+    OddSeedCount,
+    /// Seed range (counted in pairs) does not fit into an u64.
+    SeedRangeOverflow { pair: usize },
+}
+
+impl fmt::Display for AlmanacIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlmanacIssue::BrokenChain { table } => write!(
+                f,
+                "Table {} is not followed by a table with a matching source category",
+                table + 1
+            ),
+            AlmanacIssue::OverlappingSources {
+                table,
+                rule1,
+                rule2,
+            } => write!(
+                f,
+                "Rules {} and {} in table {} have overlapping source ranges",
+                rule1 + 1,
+                rule2 + 1,
+                table + 1
+            ),
+            AlmanacIssue::Overflow { table, rule } => {
+                write!(f, "Rule {} in table {} overflows", rule + 1, table + 1)
+            }
+            AlmanacIssue::OddSeedCount => write!(f, "Odd number of seeds"),
+            AlmanacIssue::SeedRangeOverflow { pair } => {
+                write!(f, "Seed range {} overflows", pair + 1)
+            }
+        }
+    }
+}
+
+/// Checks that the seeds form ranges, that the tables form a chain in file order and that the rules of every table are well-formed.
+fn validate_almanac(input: &Input) -> Vec<AlmanacIssue> {
+    let mut issues = vec![];
+
+    if !input.seeds.len().is_multiple_of(2) {
+        issues.push(AlmanacIssue::OddSeedCount);
+    }
+    for (pair, c) in input.seeds.chunks_exact(2).enumerate() {
+        if c[0].checked_add(c[1]).is_none() {
+            issues.push(AlmanacIssue::SeedRangeOverflow { pair });
+        }
+    }
+
+    for (table, w) in input.mappings.windows(2).enumerate() {
+        if w[0].destination != w[1].source {
+            issues.push(AlmanacIssue::BrokenChain { table });
+        }
+    }
+
+    for (table, mapping_table) in input.mappings.iter().enumerate() {
+        for (rule, mapping_rule) in mapping_table.rules.iter().enumerate() {
+            if mapping_rule
+                .source_start
+                .checked_add(mapping_rule.range_length)
+                .is_none()
+                || mapping_rule
+                    .destination_start
+                    .checked_add(mapping_rule.range_length)
+                    .is_none()
             {
-                value = mapping_rule.destination_start + (value - mapping_rule.source_start);
-                break;
+                issues.push(AlmanacIssue::Overflow { table, rule });
+            }
+        }
+
+        for ((rule1, r1), (rule2, r2)) in
+            mapping_table.rules.iter().enumerate().tuple_combinations()
+        {
+            let end1 = r1.source_start.saturating_add(r1.range_length);
+            let end2 = r2.source_start.saturating_add(r2.range_length);
+            if r1.source_start < end2 && r2.source_start < end1 {
+                issues.push(AlmanacIssue::OverlappingSources {
+                    table,
+                    rule1,
+                    rule2,
+                });
             }
         }
     }
 
-    value
+    issues
 }
 
 fn read_input_file<P: AsRef<Path>>(input_path: P) -> Result<Input> {
     let seeds_regex = Regex::new(r"^seeds: ([\d ]+)$").unwrap();
+    let mapping_header_regex = Regex::new(r"^(\w+)-to-(\w+) map:$").unwrap();
     let mapping_rule_regex = Regex::new(r"^(\d+) (\d+) (\d+)$").unwrap();
     let input = read_to_string(input_path)?;
     let mut lines = input.lines();
@@ -92,7 +554,12 @@ fn read_input_file<P: AsRef<Path>>(input_path: P) -> Result<Input> {
     // Read mappings until EOF
     let mut mappings = vec![];
 
-    while lines.next().is_some_and(|s| s.ends_with("map:")) {
+    while let Some(header) = lines.next() {
+        let cap = mapping_header_regex
+            .captures(header)
+            .with_context(|| format!("Could not parse mapping header: {}", header))?;
+        let source = cap.get(1).unwrap().as_str().to_owned();
+        let destination = cap.get(2).unwrap().as_str().to_owned();
         let mut rules = vec![];
 
         loop {
@@ -127,11 +594,27 @@ fn read_input_file<P: AsRef<Path>>(input_path: P) -> Result<Input> {
             rules.push(rule);
         }
 
-        mappings.push(rules);
+        mappings.push(MappingTable {
+            source,
+            destination,
+            rules,
+        });
     }
 
     let input = Input { seeds, mappings };
 
+    // Seeds and rules overflowing an u64 would make the range arithmetic panic, so they are rejected right away
+    if let Some(issue) = validate_almanac(&input).into_iter().find(|issue| {
+        matches!(
+            issue,
+            AlmanacIssue::Overflow { .. }
+                | AlmanacIssue::OddSeedCount
+                | AlmanacIssue::SeedRangeOverflow { .. }
+        )
+    }) {
+        return Err(anyhow!("Invalid almanac: {}!", issue));
+    }
+
     Ok(input)
 }
 
@@ -151,6 +634,147 @@ mod tests {
     #[test]
     fn example_second_star() {
         let input = read_input_file("../inputs/day5_example.txt").unwrap();
+        assert_eq!(map_all_seed_ranges(&input).unwrap(), 46);
         assert_eq!(brute_force_map_all_seed_ranges(&input), 46);
     }
+
+    #[test]
+    fn example_composed_mapping() {
+        let input = read_input_file("../inputs/day5_example.txt").unwrap();
+        let composed = ComposedMapping::new(&input);
+
+        for seed in 0..200 {
+            assert_eq!(composed.evaluate(seed), map_seed(&input, seed));
+        }
+        assert_eq!(composed.evaluate(u64::MAX), map_seed(&input, u64::MAX));
+
+        let segments = composed.segments();
+        assert_eq!(segments.first().unwrap().source.0, 0);
+        assert_eq!(segments.last().unwrap().source.1, u64::MAX);
+        assert!(segments.windows(2).all(|w| w[0].source.1 == w[1].source.0));
+    }
+
+    #[test]
+    fn example_inverse_mapping() {
+        let input = read_input_file("../inputs/day5_example.txt").unwrap();
+
+        // Seed 13 maps to location 35, which is the first star's answer
+        assert!(unmap_location_ranges(&input, vec![(35, 36)])
+            .iter()
+            .any(|(start, end)| *start <= 13 && 13 < *end));
+
+        // Every seed returned for a location range must map into it, and every seed mapping into it must be returned
+        let location_range = (40, 60);
+        let seed_ranges = unmap_location_ranges(&input, vec![location_range]);
+        for seed in 0..200 {
+            let location = map_seed(&input, seed);
+            let in_seed_ranges = seed_ranges
+                .iter()
+                .any(|(start, end)| *start <= seed && seed < *end);
+            let in_location_range = location_range.0 <= location && location < location_range.1;
+            assert_eq!(in_seed_ranges, in_location_range);
+        }
+
+        assert_eq!(
+            find_lowest_location_by_inverse(&input, &get_seed_ranges(&input).unwrap()),
+            Some(46)
+        );
+    }
+
+    #[test]
+    fn example_categories() {
+        let input = read_input_file("../inputs/day5_example.txt").unwrap();
+        assert!(validate_almanac(&input).is_empty());
+
+        // Seed 79 has soil 81 and humidity 78 in the puzzle description
+        assert_eq!(map_value_between(&input, "seed", "soil", 79).unwrap(), 81);
+        assert_eq!(
+            map_value_between(&input, "soil", "humidity", 81).unwrap(),
+            78
+        );
+        assert_eq!(
+            map_value_between(&input, "seed", "location", 79).unwrap(),
+            82
+        );
+        assert!(map_value_between(&input, "location", "seed", 82).is_err());
+    }
+
+    #[test]
+    fn validation_finds_issues() {
+        let input = Input {
+            seeds: vec![],
+            mappings: vec![
+                MappingTable {
+                    source: "seed".to_owned(),
+                    destination: "soil".to_owned(),
+                    rules: vec![
+                        MappingRule {
+                            destination_start: 0,
+                            source_start: 10,
+                            range_length: 10,
+                        },
+                        MappingRule {
+                            destination_start: 100,
+                            source_start: 15,
+                            range_length: 10,
+                        },
+                    ],
+                },
+                MappingTable {
+                    source: "fertilizer".to_owned(),
+                    destination: "water".to_owned(),
+                    rules: vec![MappingRule {
+                        destination_start: u64::MAX - 5,
+                        source_start: 0,
+                        range_length: 10,
+                    }],
+                },
+            ],
+        };
+
+        assert_eq!(
+            validate_almanac(&input),
+            vec![
+                AlmanacIssue::BrokenChain { table: 0 },
+                AlmanacIssue::OverlappingSources {
+                    table: 0,
+                    rule1: 0,
+                    rule2: 1
+                },
+                AlmanacIssue::Overflow { table: 1, rule: 0 },
+            ]
+        );
+    }
+
+    #[test]
+    fn overflowing_almanac_is_rejected() {
+        assert!(read_input_file("../inputs/day5_example_overflow.txt").is_err());
+        assert!(read_input_file("../inputs/day5_example_seed_overflow.txt").is_err());
+
+        let input = Input {
+            seeds: vec![79, 14, u64::MAX, 1, 55],
+            mappings: vec![],
+        };
+        assert_eq!(
+            validate_almanac(&input),
+            vec![
+                AlmanacIssue::OddSeedCount,
+                AlmanacIssue::SeedRangeOverflow { pair: 1 }
+            ]
+        );
+        assert!(get_seed_ranges(&input).is_err());
+    }
+
+    #[test]
+    fn example_range_mapping_matches_single_seeds() {
+        let input = read_input_file("../inputs/day5_example.txt").unwrap();
+
+        for seed in 0..100 {
+            let mapped = map_seed_ranges(&input, vec![(seed, seed + 1)]);
+            assert_eq!(
+                mapped,
+                vec![(map_seed(&input, seed), map_seed(&input, seed) + 1)]
+            );
+        }
+    }
 }
@@ -1,23 +1,155 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, ensure, Context, Result};
+use num::BigUint;
 use regex::Regex;
 use std::fs::read_to_string;
 use std::path::Path;
 
-fn calc_number_of_ways_to_win(time: u64, record: u64) -> usize {
+fn calc_number_of_ways_to_win(time: u128, record: u128) -> u128 {
     // Equation to solve: (x is waiting time)
-    // x * (time - x) = distance
-    // <=> -x^2 + x * time = distance
-    // <=> -x^2 + time * x - distance = 0
-    // Can be solved using quadratic formula... but anyways, brute force will also work...
-    let mut ways_to_win = 0;
-    for waiting_time in 1..time {
-        let dist = waiting_time * (time - waiting_time);
+    // x * (time - x) > record
+    // <=> x^2 - time * x + record < 0
+    // The winning waiting times lie strictly between the roots (time -/+ sqrt(time^2 - 4 * record)) / 2. Big integers are used so that
+    // time^2 cannot overflow, and the integer square root is exact (rounded down), so the candidate bound only has to be corrected
+    // by at most one step to account for rounding and for ties where the distance equals the record exactly.
+    let t = BigUint::from(time);
+    let d = BigUint::from(record);
+    let wins = |x: &BigUint| x * (&t - x) > d;
+
+    let t_squared = &t * &t;
+    let four_d = &d * 4u32;
+    if t_squared <= four_d {
+        return 0;
+    }
+    let s = (t_squared - four_d).sqrt();
+
+    let mut lo = (&t - s) / 2u32;
+    while lo <= t && !wins(&lo) {
+        lo += 1u32;
+    }
+    while lo > BigUint::from(0u32) && wins(&(&lo - 1u32)) {
+        lo -= 1u32;
+    }
+    if lo > &t / 2u32 {
+        return 0;
+    }
+
+    // Winning times are symmetric around time / 2
+    let hi = &t - &lo;
+    u128::try_from(hi - lo + 1u32).unwrap()
+}
+
+/// Charging phase of the boat: while the button is held, the speed increases by the acceleration per millisecond for the duration of
+/// the phase (or for the rest of the hold time if no duration is given).
+#[derive(Debug)]
+struct ChargePhase {
+    duration: Option<u128>,
+    acceleration: u128,
+}
+
+/// Rules for how holding the button translates into distance traveled.
+#[derive(Debug)]
+struct BoatPhysics {
+    charge_phases: Vec<ChargePhase>,
+    max_speed: Option<u128>,
+    /// Speed lost per millisecond of travel, until the boat stops.
+    friction: u128,
+}
+
+impl Default for BoatPhysics {
+    /// Physics from the puzzle: one unit of speed per millisecond of holding the button, no speed limit and no friction.
+    fn default() -> Self {
+        BoatPhysics {
+            charge_phases: vec![ChargePhase {
+                duration: None,
+                acceleration: 1,
+            }],
+            max_speed: None,
+            friction: 0,
+        }
+    }
+}
+
+impl BoatPhysics {
+    fn speed_after_holding(&self, hold_time: u128) -> Result<u128> {
+        let mut remaining = hold_time;
+        let mut speed = Some(0u128);
+
+        for phase in &self.charge_phases {
+            let phase_time = phase.duration.map_or(remaining, |d| d.min(remaining));
+            speed = speed.and_then(|s| {
+                phase
+                    .acceleration
+                    .checked_mul(phase_time)
+                    .and_then(|a| s.checked_add(a))
+            });
+            remaining -= phase_time;
+        }
+
+        // Speeds that do not fit into an u128 are fine as long as they are capped anyway
+        match (speed, self.max_speed) {
+            (Some(speed), max) => Ok(max.map_or(speed, |max| speed.min(max))),
+            (None, Some(max)) => Ok(max),
+            (None, None) => Err(anyhow!(
+                "Overflow in speed after holding the button for {} ms!",
+                hold_time
+            )),
+        }
+    }
+
+    fn distance(&self, hold_time: u128, race_time: u128) -> Result<u128> {
+        let speed = self.speed_after_holding(hold_time)?;
+        let travel_time = race_time - hold_time;
+
+        let distance = if self.friction == 0 {
+            speed.checked_mul(travel_time)
+        } else {
+            // Speed drops by the friction after every millisecond: speed + (speed - friction) + (speed - 2 * friction) + ...
+            let moving_time = travel_time.min(speed.div_ceil(self.friction));
+            moving_time.checked_mul(speed).and_then(|total| {
+                self.friction
+                    .checked_mul(moving_time)
+                    .and_then(|f| f.checked_mul(moving_time.saturating_sub(1)))
+                    .map(|lost| total - lost / 2)
+            })
+        };
+
+        distance.with_context(|| {
+            format!(
+                "Overflow in distance after holding the button for {} ms in a race of {} ms!",
+                hold_time, race_time
+            )
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct RaceResult {
+    ways_to_win: u128,
+    optimal_hold_time: u128,
+    best_distance: u128,
+}
+
+/// Simulates every possible hold time for arbitrary physics (in contrast to calc_number_of_ways_to_win(), which only works for the
+/// puzzle's physics, but in constant time). Takes O(time) steps, so it is only feasible for race times far below the u128 range.
+fn solve_race(physics: &BoatPhysics, time: u128, record: u128) -> Result<RaceResult> {
+    let mut result = RaceResult {
+        ways_to_win: 0,
+        optimal_hold_time: 0,
+        best_distance: 0,
+    };
+
+    for hold_time in 0..=time {
+        let dist = physics.distance(hold_time, time)?;
         if dist > record {
-            ways_to_win += 1;
+            result.ways_to_win += 1;
+        }
+        if dist > result.best_distance {
+            result.best_distance = dist;
+            result.optimal_hold_time = hold_time;
         }
     }
 
-    ways_to_win
+    Ok(result)
 }
 
 fn main() -> Result<()> {
@@ -26,11 +158,20 @@ fn main() -> Result<()> {
     println!(
         "Product of numbers (first star): {}",
         input
-            .into_iter()
-            .map(|(time, record)| calc_number_of_ways_to_win(time, record))
-            .product::<usize>()
+            .iter()
+            .map(|(time, record)| calc_number_of_ways_to_win(*time, *record))
+            .product::<u128>()
     );
 
+    let physics = BoatPhysics::default();
+    for (time, record) in &input {
+        println!(
+            "Optimal hold time for race with time {}: {}",
+            time,
+            solve_race(&physics, *time, *record)?.optimal_hold_time
+        );
+    }
+
     let input = read_input_file("../inputs/day6_input.txt", true)?;
     assert!(input.len() == 1);
     let (time, record) = input.first().unwrap();
@@ -40,13 +181,167 @@ fn main() -> Result<()> {
         calc_number_of_ways_to_win(*time, *record)
     );
 
+    // Trying all groupings is exponential in the number of columns, so only the groupings of both stars are shown for wide sheets
+    let sheet = read_sheet("../inputs/day6_input.txt")?;
+    let n = sheet.times.len();
+    let search = if n <= MAX_COLUMNS_FOR_ALL_INTERPRETATIONS {
+        sheet.all_interpretations()?
+    } else {
+        InterpretationSearch {
+            interpretations: vec![
+                sheet.interpretation(vec![1; n])?,
+                sheet.interpretation(vec![n])?,
+            ],
+            rejected: vec![],
+        }
+    };
+
+    for interpretation in &search.interpretations {
+        println!(
+            "Ways to win when grouping columns as {:?} (races {:?}): {}",
+            interpretation.grouping,
+            interpretation.races,
+            interpretation.ways_to_win.iter().product::<u128>()
+        );
+    }
+    for (grouping, e) in &search.rejected {
+        println!("Columns cannot be grouped as {:?}: {}", grouping, e);
+    }
+
     Ok(())
 }
 
-fn read_input_file<P: AsRef<Path>>(
-    input_path: P,
-    ignore_white_space: bool,
-) -> Result<Vec<(u64, u64)>> {
+/// Maximum number of columns for which all groupings are tried, as there are 2^(n - 1) groupings of n columns.
+const MAX_COLUMNS_FOR_ALL_INTERPRETATIONS: usize = 16;
+
+/// Columns of the sheet as written, before deciding which of them belong together because of bad kerning.
+#[derive(Debug)]
+struct Sheet {
+    times: Vec<String>,
+    distances: Vec<String>,
+}
+
+/// Reading of the sheet where each group of adjacent columns forms a single number.
+#[derive(Debug)]
+struct Interpretation {
+    grouping: Vec<usize>,
+    races: Vec<(u128, u128)>,
+    ways_to_win: Vec<u128>,
+}
+
+/// Outcome of trying every grouping: the interpretations that could be read and the groupings that could not (with the reason).
+#[derive(Debug)]
+struct InterpretationSearch {
+    interpretations: Vec<Interpretation>,
+    rejected: Vec<(Vec<usize>, anyhow::Error)>,
+}
+
+impl Sheet {
+    /// Returns the number of columns of the time and the distance line if they differ.
+    fn column_count_mismatch(&self) -> Option<(usize, usize)> {
+        if self.times.len() != self.distances.len() {
+            Some((self.times.len(), self.distances.len()))
+        } else {
+            None
+        }
+    }
+
+    /// Reads the races by concatenating the given number of adjacent columns for each race.
+    fn interpret(&self, grouping: &[usize]) -> Result<Vec<(u128, u128)>> {
+        if let Some((time_columns, distance_columns)) = self.column_count_mismatch() {
+            return Err(anyhow!(
+                "Time line has {} columns, but distance line has {} columns!",
+                time_columns,
+                distance_columns
+            ));
+        }
+        ensure!(
+            grouping.iter().sum::<usize>() == self.times.len(),
+            "Grouping does not cover all {} columns!",
+            self.times.len()
+        );
+
+        let mut races = vec![];
+        let mut col = 0;
+
+        for group_size in grouping {
+            let cols = col..col + group_size;
+            let time = self.times[cols.clone()].concat();
+            let distance = self.distances[cols].concat();
+            races.push((
+                time.parse()
+                    .with_context(|| format!("Could not parse time as number: {}", time))?,
+                distance
+                    .parse()
+                    .with_context(|| format!("Could not parse distance as number: {}", distance))?,
+            ));
+            col += group_size;
+        }
+
+        Ok(races)
+    }
+
+    /// Reads the races for the given grouping and calculates the ways to win each of them.
+    fn interpretation(&self, grouping: Vec<usize>) -> Result<Interpretation> {
+        let races = self.interpret(&grouping)?;
+        let ways_to_win = races
+            .iter()
+            .map(|(time, record)| calc_number_of_ways_to_win(*time, *record))
+            .collect();
+
+        Ok(Interpretation {
+            grouping,
+            races,
+            ways_to_win,
+        })
+    }
+
+    /// Tries every possible grouping of adjacent columns. Fails for sheets without columns, with different column counts in both lines
+    /// or with more than MAX_COLUMNS_FOR_ALL_INTERPRETATIONS columns.
+    fn all_interpretations(&self) -> Result<InterpretationSearch> {
+        let n = self.times.len();
+        if let Some((time_columns, distance_columns)) = self.column_count_mismatch() {
+            return Err(anyhow!(
+                "Time line has {} columns, but distance line has {} columns!",
+                time_columns,
+                distance_columns
+            ));
+        }
+        ensure!(n > 0, "Sheet has no columns!");
+        ensure!(
+            n <= MAX_COLUMNS_FOR_ALL_INTERPRETATIONS,
+            "Too many columns to try all groupings: {} (at most {})!",
+            n,
+            MAX_COLUMNS_FOR_ALL_INTERPRETATIONS
+        );
+
+        let mut search = InterpretationSearch {
+            interpretations: vec![],
+            rejected: vec![],
+        };
+
+        // Each of the n - 1 gaps between columns is either a separator or kerning
+        for separators in 0..1u32 << (n - 1) {
+            let mut grouping = vec![1];
+            for gap in 0..n - 1 {
+                if separators & (1 << gap) != 0 {
+                    grouping.push(1);
+                } else {
+                    *grouping.last_mut().unwrap() += 1;
+                }
+            }
+
+            match self.interpretation(grouping.clone()) {
+                Ok(interpretation) => search.interpretations.push(interpretation),
+                Err(e) => search.rejected.push((grouping, e)),
+            }
+        }
+
+        Ok(search)
+    }
+}
+
+fn read_sheet<P: AsRef<Path>>(input_path: P) -> Result<Sheet> {
     let regex_time = Regex::new(r"^Time: ([\d ]+)$").unwrap();
     let regex_distance = Regex::new(r"^Distance: ([\d ]+)$").unwrap();
 
@@ -54,35 +349,41 @@ fn read_input_file<P: AsRef<Path>>(
     let mut lines = input.lines();
 
     let cap = regex_time
-        .captures(lines.next().unwrap())
+        .captures(lines.next().context("Line with times missing!")?)
         .context("Could not parse line with times!")?;
-    let times_str = cap.get(1).unwrap().as_str();
+    let times = cap
+        .get(1)
+        .unwrap()
+        .as_str()
+        .split_whitespace()
+        .map(|s| s.to_owned())
+        .collect();
 
     let cap = regex_distance
-        .captures(lines.next().unwrap())
+        .captures(lines.next().context("Line with distances missing!")?)
         .context("Could not parse line with distances!")?;
-    let distances_str = cap.get(1).unwrap().as_str();
+    let distances = cap
+        .get(1)
+        .unwrap()
+        .as_str()
+        .split_whitespace()
+        .map(|s| s.to_owned())
+        .collect();
+
+    Ok(Sheet { times, distances })
+}
+
+fn read_input_file<P: AsRef<Path>>(
+    input_path: P,
+    ignore_white_space: bool,
+) -> Result<Vec<(u128, u128)>> {
+    let sheet = read_sheet(input_path)?;
+    let n = sheet.times.len();
 
     if ignore_white_space {
-        Ok(vec![(
-            times_str
-                .replace(' ', "")
-                .parse::<u64>()
-                .expect("Could not parse time as number!"),
-            distances_str
-                .replace(' ', "")
-                .parse::<u64>()
-                .expect("Could not parse distance as number!"),
-        )])
+        sheet.interpret(&[n])
     } else {
-        let times = times_str
-            .split_whitespace()
-            .map(|s| s.parse::<u64>().expect("Could not parse time as number!"));
-        let distances = distances_str.split_whitespace().map(|s| {
-            s.parse::<u64>()
-                .expect("Could not parse distance as number!")
-        });
-        Ok(times.zip(distances).collect())
+        sheet.interpret(&vec![1; n])
     }
 }
 
@@ -98,7 +399,7 @@ mod tests {
             input
                 .into_iter()
                 .map(|(time, record)| calc_number_of_ways_to_win(time, record))
-                .product::<usize>(),
+                .product::<u128>(),
             288
         );
     }
@@ -111,4 +412,157 @@ mod tests {
 
         assert_eq!(calc_number_of_ways_to_win(*time, *record), 71503)
     }
+
+    #[test]
+    fn closed_form_matches_brute_force() {
+        for time in 0..60u128 {
+            for record in 0..(time * time / 4 + 3) {
+                let brute_force = (0..=time).filter(|x| x * (time - x) > record).count();
+                assert_eq!(
+                    calc_number_of_ways_to_win(time, record),
+                    brute_force as u128,
+                    "time {}, record {}",
+                    time,
+                    record
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn example_physics() {
+        let input = read_input_file("../inputs/day6_example.txt", false).unwrap();
+
+        // Default physics must match the closed-form solution
+        for (time, record) in &input {
+            let result = solve_race(&BoatPhysics::default(), *time, *record).unwrap();
+            assert_eq!(
+                result.ways_to_win,
+                calc_number_of_ways_to_win(*time, *record)
+            );
+            assert_eq!(result.optimal_hold_time, time / 2);
+        }
+
+        // First race (7 ms, record 9) with doubled acceleration but a speed cap of 6: hold times 1-6 reach 12, 20, 24, 18, 12 and 6
+        let physics = BoatPhysics {
+            charge_phases: vec![ChargePhase {
+                duration: None,
+                acceleration: 2,
+            }],
+            max_speed: Some(6),
+            friction: 0,
+        };
+        assert_eq!(
+            solve_race(&physics, 7, 9).unwrap(),
+            RaceResult {
+                ways_to_win: 5,
+                optimal_hold_time: 3,
+                best_distance: 24
+            }
+        );
+
+        // Charging with 3 per ms for the first millisecond and 1 per ms afterwards, with a friction of 1: holding 3 ms gives a speed
+        // of 5 and travels 5 + 4 + 3 + 2 = 14 in the remaining 4 ms, while holding 4 ms travels 6 + 5 + 4 = 15
+        let physics = BoatPhysics {
+            charge_phases: vec![
+                ChargePhase {
+                    duration: Some(1),
+                    acceleration: 3,
+                },
+                ChargePhase {
+                    duration: None,
+                    acceleration: 1,
+                },
+            ],
+            max_speed: None,
+            friction: 1,
+        };
+        assert_eq!(physics.distance(3, 7).unwrap(), 14);
+        assert_eq!(
+            solve_race(&physics, 7, 9).unwrap(),
+            RaceResult {
+                ways_to_win: 4,
+                optimal_hold_time: 4,
+                best_distance: 15
+            }
+        );
+
+        // Distances beyond u128 are reported instead of counting as a win, unless the speed is capped
+        let mut physics = BoatPhysics {
+            charge_phases: vec![ChargePhase {
+                duration: None,
+                acceleration: u128::MAX,
+            }],
+            max_speed: None,
+            friction: 0,
+        };
+        assert!(solve_race(&physics, 3, 0).is_err());
+        physics.max_speed = Some(10);
+        assert_eq!(solve_race(&physics, 3, 0).unwrap().best_distance, 20);
+        assert!(BoatPhysics::default()
+            .distance(1 << 64, (1 << 65) + 1)
+            .is_err());
+    }
+
+    #[test]
+    fn example_kerning() {
+        let sheet = read_sheet("../inputs/day6_example.txt").unwrap();
+        assert_eq!(
+            sheet.interpret(&[2, 1]).unwrap(),
+            vec![(715, 940), (30, 200)]
+        );
+        assert!(sheet.interpret(&[2, 2]).is_err());
+
+        // Three columns can be grouped in four ways, which include both stars
+        let search = sheet.all_interpretations().unwrap();
+        assert_eq!(search.interpretations.len(), 4);
+        assert!(search.rejected.is_empty());
+        let ways: Vec<_> = search
+            .interpretations
+            .iter()
+            .map(|i| (i.grouping.clone(), i.ways_to_win.iter().product::<u128>()))
+            .collect();
+        assert!(ways.contains(&(vec![1, 1, 1], 288)));
+        assert!(ways.contains(&(vec![3], 71503)));
+
+        let sheet = Sheet {
+            times: vec!["7".to_owned(), "15".to_owned()],
+            distances: vec!["9".to_owned()],
+        };
+        assert_eq!(sheet.column_count_mismatch(), Some((2, 1)));
+        assert!(sheet.interpret(&[1, 1]).is_err());
+        assert!(sheet.all_interpretations().is_err());
+
+        // Concatenating both columns does not fit into an u128, which is reported instead of dropped
+        let sheet = Sheet {
+            times: vec!["1".repeat(20); 2],
+            distances: vec!["1".repeat(20); 2],
+        };
+        let search = sheet.all_interpretations().unwrap();
+        assert_eq!(search.interpretations.len(), 1);
+        assert_eq!(search.interpretations[0].grouping, vec![1, 1]);
+        assert_eq!(search.rejected.len(), 1);
+        assert_eq!(search.rejected[0].0, vec![2]);
+
+        // Trying all groupings of 65 columns would take 2^64 steps
+        let sheet = Sheet {
+            times: vec!["1".to_owned(); 65],
+            distances: vec!["0".to_owned(); 65],
+        };
+        assert!(sheet.all_interpretations().is_err());
+        assert_eq!(
+            sheet.interpretation(vec![1; 65]).unwrap().ways_to_win.len(),
+            65
+        );
+    }
+
+    #[test]
+    fn race_time_beyond_u64() {
+        // Holding for 2^64 reaches 2^128 and beats the record by one, while holding one unit longer or shorter reaches exactly the
+        // record and does not win
+        let time = 1u128 << 65;
+        let record = u128::MAX;
+        assert_eq!(calc_number_of_ways_to_win(time, record), 1);
+        assert_eq!(calc_number_of_ways_to_win(time, 0), time - 1);
+    }
 }
@@ -1,13 +1,12 @@
-use anyhow::{ensure, Context, Result};
-use itertools::Itertools;
+use anyhow::{anyhow, ensure, Context, Result};
+use rand::prelude::*;
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
-use std::fs::read_to_string;
+use std::fs::{create_dir_all, read_to_string, write};
 use std::path::Path;
 
-#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 enum Card {
-    Joker,
     N2,
     N3,
     N4,
@@ -23,6 +22,22 @@ enum Card {
     A,
 }
 
+const ALL_CARDS: [Card; 13] = [
+    Card::N2,
+    Card::N3,
+    Card::N4,
+    Card::N5,
+    Card::N6,
+    Card::N7,
+    Card::N8,
+    Card::N9,
+    Card::T,
+    Card::J,
+    Card::Q,
+    Card::K,
+    Card::A,
+];
+
 impl TryFrom<char> for Card {
     type Error = ();
 
@@ -46,32 +61,163 @@ impl TryFrom<char> for Card {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+impl From<Card> for char {
+    fn from(c: Card) -> char {
+        match c {
+            Card::N2 => '2',
+            Card::N3 => '3',
+            Card::N4 => '4',
+            Card::N5 => '5',
+            Card::N6 => '6',
+            Card::N7 => '7',
+            Card::N8 => '8',
+            Card::N9 => '9',
+            Card::T => 'T',
+            Card::J => 'J',
+            Card::Q => 'Q',
+            Card::K => 'K',
+            Card::A => 'A',
+        }
+    }
+}
+
+fn cards_to_string(cards: &[Card]) -> String {
+    cards.iter().map(|c| char::from(*c)).collect()
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+enum Suit {
+    Clubs,
+    Diamonds,
+    Hearts,
+    Spades,
+}
+
+impl TryFrom<char> for Suit {
+    type Error = ();
+
+    fn try_from(c: char) -> Result<Suit, Self::Error> {
+        match c {
+            'c' => Ok(Suit::Clubs),
+            'd' => Ok(Suit::Diamonds),
+            'h' => Ok(Suit::Hearts),
+            's' => Ok(Suit::Spades),
+            _ => Err(()),
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+enum HandType {
+    HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    Straight,
+    Flush,
+    FullHouse,
+    FourOfAKind,
+    StraightFlush,
+    FiveOfAKind,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+enum TieBreak {
+    /// Compare the cards one by one in the order they were dealt (Camel Cards).
+    DealtOrder,
+    /// Compare the cards one by one after sorting them from strongest to weakest (similar to poker).
+    Sorted,
+}
+
+/// Rules of the card game that determine how hands are parsed, classified and ranked. Straights always follow the natural order from 2
+/// to A, independent of card_order, and aces only count high (A-2-3-4-5 is no straight).
+#[derive(Debug)]
+struct GameRules {
+    hand_size: usize,
+    /// All card ranks from weakest to strongest.
+    card_order: Vec<Card>,
+    /// Cards that act like whatever card results in the best hand type.
+    wild_cards: Vec<Card>,
+    /// Hand types from weakest to strongest. Types not in the list are never assigned, hands that cannot form any of them are
+    /// rejected.
+    hand_types: Vec<HandType>,
+    tie_break: TieBreak,
+}
+
+impl GameRules {
+    /// Rules from the first part of the puzzle.
+    fn camel_cards() -> GameRules {
+        GameRules {
+            hand_size: 5,
+            card_order: ALL_CARDS.to_vec(),
+            wild_cards: vec![],
+            hand_types: vec![
+                HandType::HighCard,
+                HandType::OnePair,
+                HandType::TwoPair,
+                HandType::ThreeOfAKind,
+                HandType::FullHouse,
+                HandType::FourOfAKind,
+                HandType::FiveOfAKind,
+            ],
+            tie_break: TieBreak::DealtOrder,
+        }
+    }
+
+    /// Rules from the second part of the puzzle: J cards are jokers, which are wild but the weakest card in the tie-breaker.
+    fn camel_cards_with_jokers() -> GameRules {
+        let mut card_order = vec![Card::J];
+        card_order.extend(ALL_CARDS.iter().filter(|c| **c != Card::J));
+
+        GameRules {
+            card_order,
+            wild_cards: vec![Card::J],
+            ..GameRules::camel_cards()
+        }
+    }
+
+    fn card_rank(&self, c: Card) -> Result<usize> {
+        self.card_order
+            .iter()
+            .position(|o| *o == c)
+            .with_context(|| format!("Card {} is not ranked by the game rules!", char::from(c)))
+    }
+
+    fn hand_type_rank(&self, ht: HandType) -> Result<usize> {
+        self.hand_types
+            .iter()
+            .position(|o| *o == ht)
+            .with_context(|| format!("Hand type {:?} is not ranked by the game rules!", ht))
+    }
+}
+
+#[derive(Debug)]
 struct Hand {
-    cards: [Card; 5],
+    cards: Vec<Card>,
+    suits: Option<Vec<Suit>>,
     bid: usize,
+    hand_type: HandType,
+    /// Cards that the wild cards (in dealt order) are best replaced by to form the hand type.
+    wild_replacements: Vec<Card>,
+    /// Rank of the hand type followed by the card ranks in tie-breaker order, precomputed from the game rules.
+    sort_key: Vec<usize>,
 }
 
+/// Hands are equal if they are equally strong, matching the ordering (e.g., the same cards in a different order with a sorted
+/// tie-breaker).
+impl PartialEq for Hand {
+    fn eq(&self, other: &Self) -> bool {
+        self.sort_key == other.sort_key
+    }
+}
+
+impl Eq for Hand {}
+
 impl Ord for Hand {
     fn cmp(&self, other: &Self) -> Ordering {
-        let ht_self = self.calc_hand_type_with_replaced_jokers();
-        let ht_other = other.calc_hand_type_with_replaced_jokers();
-
-        let cmp = ht_self.cmp(&ht_other);
-        match cmp {
-            Ordering::Equal => {
-                // Run tie-breaker
-                for (c1, c2) in self.cards.iter().zip(other.cards.iter()) {
-                    let cmp2 = c1.cmp(c2);
-                    if cmp2 != Ordering::Equal {
-                        return cmp2;
-                    }
-                }
-
-                Ordering::Equal
-            }
-            _ => cmp,
-        }
+        self.sort_key.cmp(&other.sort_key)
     }
 }
 
@@ -82,166 +228,460 @@ impl PartialOrd for Hand {
 }
 
 impl Hand {
-    /// Calculate best hand type using a brute force search where each joker is replaced by every possible hand.
-    fn calc_hand_type_with_replaced_jokers(&self) -> HandType {
-        let cards_without_jokers: Vec<Card> = self
-            .cards
+    fn new(
+        cards: Vec<Card>,
+        suits: Option<Vec<Suit>>,
+        bid: usize,
+        rules: &GameRules,
+    ) -> Result<Hand> {
+        let hand_type = calc_hand_type(&cards, suits.as_deref(), rules)?;
+        let wild_replacements = calc_wild_replacements(&cards, hand_type, rules);
+
+        let mut card_ranks = cards
             .iter()
-            .filter(|c| **c != Card::Joker)
-            .cloned()
-            .collect();
-        let joker_count = 5 - cards_without_jokers.len();
-
-        if joker_count > 0 {
-            let possible_cards = [
-                Card::N2,
-                Card::N3,
-                Card::N4,
-                Card::N5,
-                Card::N6,
-                Card::N7,
-                Card::N8,
-                Card::N9,
-                Card::T,
-                Card::J,
-                Card::Q,
-                Card::K,
-                Card::A,
-            ];
-
-            let cards_to_add = (0..joker_count)
-                .map(|_| possible_cards.into_iter())
-                .multi_cartesian_product();
-
-            cards_to_add
-                .map(|cta| {
-                    let mut cards_vec = cards_without_jokers.clone();
-                    cards_vec.append(&mut cta.clone());
-
-                    let h = Hand {
-                        cards: cards_vec.try_into().unwrap(),
-                        bid: self.bid,
-                    };
-                    h.calc_hand_type_without_joker()
-                })
-                .max()
-                .unwrap()
-        } else {
-            self.calc_hand_type_without_joker()
+            .map(|c| rules.card_rank(*c))
+            .collect::<Result<Vec<_>>>()?;
+        if let TieBreak::Sorted = rules.tie_break {
+            card_ranks.sort_unstable_by(|a, b| b.cmp(a));
         }
+
+        let mut sort_key = vec![rules.hand_type_rank(hand_type)?];
+        sort_key.extend(card_ranks);
+
+        Ok(Hand {
+            cards,
+            suits,
+            bid,
+            hand_type,
+            wild_replacements,
+            sort_key,
+        })
     }
+}
 
-    /// Calculate hand type assuming that there are no jokers (or jokers already have been substituted).
-    fn calc_hand_type_without_joker(&self) -> HandType {
-        // Card how often types of cards appear in this hand
-        let mut card_counts: BTreeMap<Card, u8> = BTreeMap::new();
+/// Returns whether the hand can form the given hand type if the wild cards are replaced optimally. This is decided directly from the
+/// card counts: for example, a full house needs the wild cards to fill up the most frequent card to three and the second most frequent
+/// card to two.
+fn is_hand_type_possible(
+    ht: HandType,
+    cards: &[Card],
+    suits: Option<&[Suit]>,
+    rules: &GameRules,
+) -> bool {
+    let is_wild = |c: &Card| rules.wild_cards.contains(c);
+    let wild_count = cards.iter().filter(|c| is_wild(c)).count();
+
+    // Count how often types of cards appear in this hand (only the two highest counts matter)
+    let mut card_counts = [0usize; 13];
+    for c in cards.iter().filter(|c| !is_wild(c)) {
+        card_counts[*c as usize] += 1;
+    }
+    card_counts.sort_unstable_by(|a, b| b.cmp(a));
+    let missing = |needed: usize, count: usize| needed.saturating_sub(count);
+    let n_of_a_kind = |n: usize| n <= rules.hand_size && missing(n, card_counts[0]) <= wild_count;
 
-        for c in self.cards {
-            *card_counts.entry(c).or_default() += 1;
+    // Straights consist of distinct cards whose natural ranks span at most the hand size (wild cards fill the gaps)
+    let is_straight = || {
+        let ranks: Vec<_> = ALL_CARDS
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| cards.contains(c) && !is_wild(c))
+            .map(|(i, _)| i)
+            .collect();
+        let distinct = ranks.len() + wild_count == cards.len();
+        distinct
+            && cards.len() <= ALL_CARDS.len()
+            && ranks.last().unwrap_or(&0) - ranks.first().unwrap_or(&0) < cards.len()
+    };
+
+    // Flushes need suits, wild cards can take any suit
+    let is_flush = || {
+        suits.is_some_and(|suits| {
+            let mut non_wild_suits = cards.iter().zip(suits).filter(|(c, _)| !is_wild(c));
+            match non_wild_suits.next() {
+                Some((_, s)) => non_wild_suits.all(|(_, s2)| s == s2),
+                None => true,
+            }
+        })
+    };
+
+    match ht {
+        HandType::HighCard => true,
+        HandType::OnePair => n_of_a_kind(2),
+        HandType::TwoPair => {
+            rules.hand_size >= 4
+                && missing(2, card_counts[0]) + missing(2, card_counts[1]) <= wild_count
         }
+        HandType::ThreeOfAKind => n_of_a_kind(3),
+        HandType::Straight => is_straight(),
+        HandType::Flush => is_flush(),
+        HandType::FullHouse => {
+            rules.hand_size >= 5
+                && missing(3, card_counts[0]) + missing(2, card_counts[1]) <= wild_count
+        }
+        HandType::FourOfAKind => n_of_a_kind(4),
+        HandType::StraightFlush => is_straight() && is_flush(),
+        HandType::FiveOfAKind => n_of_a_kind(5),
+    }
+}
 
-        let count_values: Vec<u8> = card_counts.values().cloned().collect();
-
-        if count_values.contains(&5) {
-            HandType::FiveOfAKind
-        } else if count_values.contains(&4) {
-            HandType::FourOfAKind
-        } else if count_values.contains(&3) && count_values.contains(&2) {
-            HandType::FullHouse
-        } else if count_values.contains(&3) {
-            HandType::ThreeOfAKind
-        } else if count_values.iter().filter(|n| **n == 2).count() == 2 {
-            HandType::TwoPair
-        } else if count_values.contains(&2) {
-            HandType::OnePair
-        } else {
-            HandType::HighCard
+/// Determines the strongest hand type (according to the rules' ranking) that the hand can form.
+fn calc_hand_type(cards: &[Card], suits: Option<&[Suit]>, rules: &GameRules) -> Result<HandType> {
+    rules
+        .hand_types
+        .iter()
+        .rev()
+        .find(|ht| is_hand_type_possible(**ht, cards, suits, rules))
+        .cloned()
+        .ok_or_else(|| {
+            anyhow!(
+                "Hand {} does not form any hand type of the game rules!",
+                cards_to_string(cards)
+            )
+        })
+}
+
+/// Determines which card each wild card (in dealt order) is best replaced by to form the given hand type: wild cards fill the gaps of
+/// a straight, fill up the two most frequent cards for a full house or two pairs and otherwise join the most frequent card.
+fn calc_wild_replacements(cards: &[Card], hand_type: HandType, rules: &GameRules) -> Vec<Card> {
+    let is_wild = |c: &Card| rules.wild_cards.contains(c);
+    let wild_count = cards.iter().filter(|c| is_wild(c)).count();
+    if wild_count == 0 {
+        return vec![];
+    }
+
+    // Non-wild cards from most to least frequent in the hand (stronger cards first on ties)
+    let mut candidates: Vec<(usize, usize, Card)> = rules
+        .card_order
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| !is_wild(c))
+        .map(|(rank, c)| (cards.iter().filter(|h| *h == c).count(), rank, *c))
+        .collect();
+    candidates.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+
+    let mut replacements = vec![];
+
+    match hand_type {
+        HandType::Straight | HandType::StraightFlush => {
+            // Use the highest window of consecutive ranks that contains all non-wild cards
+            let ranks: Vec<_> = ALL_CARDS
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| cards.contains(c) && !is_wild(c))
+                .map(|(i, _)| i)
+                .collect();
+            let start = ranks.first().map_or(ALL_CARDS.len() - cards.len(), |r| {
+                (*r).min(ALL_CARDS.len() - cards.len())
+            });
+            replacements.extend(
+                (start..start + cards.len())
+                    .filter(|r| !ranks.contains(r))
+                    .map(|r| ALL_CARDS[r]),
+            );
         }
+        HandType::TwoPair | HandType::FullHouse => {
+            let needed = if hand_type == HandType::FullHouse {
+                [3, 2]
+            } else {
+                [2, 2]
+            };
+            for ((count, _, card), need) in candidates.iter().zip(needed) {
+                replacements.extend((*count..need).map(|_| *card));
+            }
+        }
+        _ => {}
+    }
+
+    // Remaining wild cards join the most frequent card
+    if let Some((_, _, card)) = candidates.first() {
+        replacements.resize(wild_count.max(replacements.len()), *card);
     }
+    replacements.truncate(wild_count);
+    replacements
 }
 
-#[derive(Debug, Eq, Ord, PartialEq, PartialOrd)]
-enum HandType {
-    HighCard,
-    OnePair,
-    TwoPair,
-    ThreeOfAKind,
-    FullHouse,
-    FourOfAKind,
-    FiveOfAKind,
+/// Hand with its position in the ranking (starting at 1) and the amount it contributes to the total winnings.
+#[derive(Debug)]
+struct RankedHand {
+    rank: usize,
+    hand: Hand,
+    winnings: usize,
 }
 
-fn calc_total_winnings(mut hands: Vec<Hand>) -> usize {
+fn rank_hands(mut hands: Vec<Hand>) -> Vec<RankedHand> {
     // Sort hands by ascending strength
     hands.sort_unstable();
 
-    // For debugging
-    /* for h in &hands {
-        println!(
-            "{:?}: {:?} (bid {})",
-            h.cards,
-            h.calc_hand_type_with_replaced_jokers(),
-            h.bid
-        )
-    } */
-
     hands
-        .iter()
+        .into_iter()
         .enumerate()
-        .map(|(i, hand)| hand.bid * (i + 1))
-        .sum()
+        .map(|(i, hand)| RankedHand {
+            rank: i + 1,
+            winnings: hand.bid * (i + 1),
+            hand,
+        })
+        .collect()
+}
+
+fn calc_total_winnings(ranked_hands: &[RankedHand]) -> usize {
+    ranked_hands.iter().map(|rh| rh.winnings).sum()
+}
+
+/// Renders the ranked hands as a table with one line per hand.
+fn format_report_table(ranked_hands: &[RankedHand]) -> String {
+    let mut s = String::from("Rank | Hand | Bid | Type | Wild cards replaced by | Winnings\n");
+
+    for rh in ranked_hands {
+        s.push_str(&format!(
+            "{} | {} | {} | {:?} | {} | {}\n",
+            rh.rank,
+            cards_to_string(&rh.hand.cards),
+            rh.hand.bid,
+            rh.hand.hand_type,
+            cards_to_string(&rh.hand.wild_replacements),
+            rh.winnings
+        ));
+    }
+
+    s
+}
+
+/// Renders the ranked hands as a JSON array.
+fn format_report_json(ranked_hands: &[RankedHand]) -> String {
+    let entries: Vec<_> = ranked_hands
+        .iter()
+        .map(|rh| {
+            format!(
+                "{{\"rank\":{},\"hand\":\"{}\",\"bid\":{},\"hand_type\":\"{:?}\",\"wild_replacements\":\"{}\",\"winnings\":{}}}",
+                rh.rank,
+                cards_to_string(&rh.hand.cards),
+                rh.hand.bid,
+                rh.hand.hand_type,
+                cards_to_string(&rh.hand.wild_replacements),
+                rh.winnings
+            )
+        })
+        .collect();
+
+    format!("[{}]", entries.join(","))
+}
+
+/// Deck that hands are dealt from (without putting cards back during a deal).
+#[derive(Clone, Debug)]
+struct Deck {
+    cards: Vec<(Card, Option<Suit>)>,
+}
+
+impl Deck {
+    /// Standard deck with four cards of each rank, which optionally carry the four suits.
+    fn standard(with_suits: bool) -> Deck {
+        let suits = [Suit::Clubs, Suit::Diamonds, Suit::Hearts, Suit::Spades];
+        let cards = ALL_CARDS
+            .iter()
+            .flat_map(|c| suits.iter().map(move |s| (*c, with_suits.then_some(*s))))
+            .collect();
+
+        Deck { cards }
+    }
+
+    /// Returns the remaining deck after the cards of the given hand have been taken out. Cards without a suit match any suit.
+    fn without_hand(&self, hand: &Hand) -> Result<Deck> {
+        let mut cards = self.cards.clone();
+
+        for (i, c) in hand.cards.iter().enumerate() {
+            let suit = hand.suits.as_ref().map(|suits| suits[i]);
+            let pos = cards
+                .iter()
+                .position(|(dc, ds)| dc == c && (suit.is_none() || ds.is_none() || *ds == suit))
+                .with_context(|| {
+                    format!(
+                        "Hand {} cannot be dealt from the deck!",
+                        cards_to_string(&hand.cards)
+                    )
+                })?;
+            cards.swap_remove(pos);
+        }
+
+        Ok(Deck { cards })
+    }
+
+    /// Deals the given number of hands from a single shuffle, so that no card is dealt twice. The cards stay in the (reordered) deck
+    /// for the next deal.
+    fn deal<R: Rng>(&mut self, rng: &mut R, rules: &GameRules, count: usize) -> Result<Vec<Hand>> {
+        let needed = count * rules.hand_size;
+        ensure!(
+            needed <= self.cards.len(),
+            "Deck of {} cards cannot deal {} hands of {} cards!",
+            self.cards.len(),
+            count,
+            rules.hand_size
+        );
+
+        let (dealt, _) = self.cards.partial_shuffle(rng, needed);
+        dealt
+            .chunks(rules.hand_size)
+            .map(|chunk| {
+                let suits: Option<Vec<Suit>> = chunk.iter().map(|(_, s)| *s).collect();
+                Hand::new(chunk.iter().map(|(c, _)| *c).collect(), suits, 0, rules)
+            })
+            .collect()
+    }
+}
+
+/// Deals the given number of random hands and returns how often each hand type occurred (as a fraction of all hands).
+fn estimate_hand_type_frequencies(
+    deck: &Deck,
+    rules: &GameRules,
+    trials: usize,
+    seed: u64,
+) -> Result<BTreeMap<HandType, f64>> {
+    // Work on a copy so that the same seed gives the same estimate regardless of earlier deals
+    let mut deck = deck.clone();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut counts: BTreeMap<HandType, usize> = BTreeMap::new();
+
+    for _ in 0..trials {
+        for hand in deck.deal(&mut rng, rules, 1)? {
+            *counts.entry(hand.hand_type).or_default() += 1;
+        }
+    }
+
+    Ok(counts
+        .into_iter()
+        .map(|(ht, count)| (ht, count as f64 / trials as f64))
+        .collect())
+}
+
+/// Estimates the rank (1 = weakest) that the given hand reaches on average against the given number of opponents, who are dealt
+/// from the rest of the deck, with ties counting as half a win.
+fn estimate_expected_rank(
+    hand: &Hand,
+    deck: &Deck,
+    rules: &GameRules,
+    opponents: usize,
+    trials: usize,
+    seed: u64,
+) -> Result<f64> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut rest = deck.without_hand(hand)?;
+    let mut rank_sum = 0.0;
+
+    for _ in 0..trials {
+        rank_sum += 1.0;
+        for opponent in rest.deal(&mut rng, rules, opponents)? {
+            match hand.cmp(&opponent) {
+                Ordering::Greater => rank_sum += 1.0,
+                Ordering::Equal => rank_sum += 0.5,
+                Ordering::Less => {}
+            }
+        }
+    }
+
+    Ok(rank_sum / trials as f64)
 }
 
 fn main() -> Result<()> {
-    let input_without_jokers = read_input_file("../inputs/day7_input.txt", false)?;
-    let input_with_jokers = read_input_file("../inputs/day7_input.txt", true)?;
+    create_dir_all("../outputs")?;
+    let deck = Deck::standard(false);
+
+    for (name, rules) in [
+        ("without_jokers", GameRules::camel_cards()),
+        ("with_jokers", GameRules::camel_cards_with_jokers()),
+    ] {
+        let ranked_hands = rank_hands(read_input_file("../inputs/day7_input.txt", &rules)?);
+        println!(
+            "Total winnings {}: {}",
+            name.replace('_', " "),
+            calc_total_winnings(&ranked_hands)
+        );
+
+        let path = format!("../outputs/day7_report_{}", name);
+        write(format!("{}.txt", path), format_report_table(&ranked_hands))?;
+        write(format!("{}.json", path), format_report_json(&ranked_hands))?;
+        println!("Report written to {}.txt and {}.json", path, path);
+
+        // Hands in the input may not be dealable from a standard deck (e.g., five of a kind), which is reported but not fatal
+        for rh in [ranked_hands.first(), ranked_hands.last()]
+            .into_iter()
+            .flatten()
+        {
+            match estimate_expected_rank(&rh.hand, &deck, &rules, 5, 10_000, 0) {
+                Ok(rank) => println!(
+                    "Expected rank of {} against 5 random opponents: {:.3}",
+                    cards_to_string(&rh.hand.cards),
+                    rank
+                ),
+                Err(e) => println!(
+                    "Expected rank of {} cannot be estimated: {}",
+                    cards_to_string(&rh.hand.cards),
+                    e
+                ),
+            }
+        }
+    }
 
-    println!(
-        "Total winnings without jokers: {}",
-        calc_total_winnings(input_without_jokers)
-    );
-    println!(
-        "Total winnings with jokers: {}",
-        calc_total_winnings(input_with_jokers)
-    );
+    for (name, rules) in [
+        ("without jokers", GameRules::camel_cards()),
+        ("with jokers", GameRules::camel_cards_with_jokers()),
+    ] {
+        println!("Estimated hand type frequencies {}:", name);
+        for (ht, freq) in estimate_hand_type_frequencies(&deck, &rules, 100_000, 0)? {
+            println!("  {:?}: {:.4}", ht, freq);
+        }
+    }
 
     Ok(())
 }
 
-fn read_input_file<P: AsRef<Path>>(input_path: P, parse_j_as_joker: bool) -> Result<Vec<Hand>> {
+fn read_input_file<P: AsRef<Path>>(input_path: P, rules: &GameRules) -> Result<Vec<Hand>> {
     let input = read_to_string(input_path)?;
-    let res: Vec<Result<_>> = input
-        .lines()
-        .map(|l| parse_input_line(l, parse_j_as_joker))
-        .collect();
+    let res: Vec<Result<_>> = input.lines().map(|l| parse_input_line(l, rules)).collect();
     res.into_iter().collect()
 }
 
-fn parse_input_line(line: &str, parse_j_as_joker: bool) -> Result<Hand> {
+/// Parses a line with a hand and a bid. Each card can optionally be followed by its suit (e.g., "Th" for the ten of hearts).
+fn parse_input_line(line: &str, rules: &GameRules) -> Result<Hand> {
     let mut elements = line.split(' ');
 
     let cards_str = elements.next().context("Could not extract hand string!")?;
-    ensure!(cards_str.len() == 5, "Hand does not consist of five cards!");
-    let cards_vec: Vec<_> = cards_str
-        .chars()
-        .filter_map(|c| {
-            if parse_j_as_joker && c == 'J' {
-                Some(Card::Joker)
-            } else {
-                c.try_into().ok()
-            }
-        })
-        .collect();
-    ensure!(cards_vec.len() == 5, "Card could not be parsed!");
+    let mut cards = vec![];
+    let mut suits = vec![];
+    let mut chars = cards_str.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let card: Card = c
+            .try_into()
+            .ok()
+            .with_context(|| format!("Card could not be parsed: {}", c))?;
+        cards.push(card);
+
+        if let Some(suit) = chars.peek().and_then(|s| Suit::try_from(*s).ok()) {
+            suits.push(suit);
+            chars.next();
+        }
+    }
+
+    ensure!(
+        cards.len() == rules.hand_size,
+        "Hand does not consist of {} cards!",
+        rules.hand_size
+    );
+    ensure!(
+        suits.is_empty() || suits.len() == cards.len(),
+        "Either all or no cards need a suit!"
+    );
 
     let bid_str = elements.next().context("Could not extract bid!")?;
 
-    Ok(Hand {
-        cards: cards_vec.try_into().unwrap(),
-        bid: bid_str
+    Hand::new(
+        cards,
+        if suits.is_empty() { None } else { Some(suits) },
+        bid_str
             .parse()
             .context("Could not parse bid as a number!")?,
-    })
+        rules,
+    )
 }
 
 #[cfg(test)]
@@ -250,13 +690,176 @@ mod tests {
 
     #[test]
     fn example_first_star() {
-        let input = read_input_file("../inputs/day7_example.txt", false).unwrap();
-        assert_eq!(calc_total_winnings(input), 6440);
+        let input =
+            read_input_file("../inputs/day7_example.txt", &GameRules::camel_cards()).unwrap();
+        assert_eq!(calc_total_winnings(&rank_hands(input)), 6440);
     }
 
     #[test]
     fn example_second_star() {
-        let input = read_input_file("../inputs/day7_example.txt", true).unwrap();
-        assert_eq!(calc_total_winnings(input), 5905);
+        let input = read_input_file(
+            "../inputs/day7_example.txt",
+            &GameRules::camel_cards_with_jokers(),
+        )
+        .unwrap();
+        assert_eq!(calc_total_winnings(&rank_hands(input)), 5905);
+    }
+
+    #[test]
+    fn joker_hand_types() {
+        let rules = GameRules::camel_cards_with_jokers();
+        let hand_type = |s: &str| {
+            parse_input_line(&format!("{} 1", s), &rules)
+                .unwrap()
+                .hand_type
+        };
+        assert_eq!(hand_type("JJJJJ"), HandType::FiveOfAKind);
+        assert_eq!(hand_type("KTJJT"), HandType::FourOfAKind);
+        assert_eq!(hand_type("2233J"), HandType::FullHouse);
+        assert_eq!(hand_type("234JJ"), HandType::ThreeOfAKind);
+        assert_eq!(hand_type("2345J"), HandType::OnePair);
+        assert_eq!(hand_type("23456"), HandType::HighCard);
+    }
+
+    #[test]
+    fn example_report() {
+        let input = read_input_file(
+            "../inputs/day7_example.txt",
+            &GameRules::camel_cards_with_jokers(),
+        )
+        .unwrap();
+        let ranked_hands = rank_hands(input);
+
+        // KTJJT is the strongest hand, with both jokers becoming tens
+        let best = ranked_hands.last().unwrap();
+        assert_eq!(cards_to_string(&best.hand.cards), "KTJJT");
+        assert_eq!(best.hand.wild_replacements, vec![Card::T, Card::T]);
+        assert_eq!(best.winnings, 220 * 5);
+
+        let table = format_report_table(&ranked_hands);
+        assert!(table.contains("5 | KTJJT | 220 | FourOfAKind | TT | 1100"));
+        let json = format_report_json(&ranked_hands);
+        assert!(json
+            .starts_with("[{\"rank\":1,\"hand\":\"32T3K\",\"bid\":765,\"hand_type\":\"OnePair\""));
+    }
+
+    #[test]
+    fn wild_replacements() {
+        let rules = GameRules::camel_cards_with_jokers();
+        let replacements = |s: &str| {
+            parse_input_line(&format!("{} 1", s), &rules)
+                .unwrap()
+                .wild_replacements
+        };
+        assert_eq!(replacements("JJJJJ"), vec![Card::A; 5]);
+        assert_eq!(replacements("2233J"), vec![Card::N3]);
+        assert_eq!(replacements("2345J"), vec![Card::N5]);
+        assert!(replacements("23456").is_empty());
+
+        let mut straight_rules = GameRules::camel_cards_with_jokers();
+        straight_rules.hand_types.insert(4, HandType::Straight);
+        let hand = parse_input_line("9TJQK 1", &straight_rules).unwrap();
+        assert_eq!(hand.hand_type, HandType::Straight);
+        assert_eq!(hand.wild_replacements, vec![Card::J]);
+        let hand = parse_input_line("JTJQK 1", &straight_rules).unwrap();
+        assert_eq!(hand.wild_replacements, vec![Card::J, Card::A]);
+    }
+
+    #[test]
+    fn simulation() {
+        let deck = Deck::standard(false);
+        let rules = GameRules::camel_cards();
+
+        // Same seed must give the same estimate
+        let freqs = estimate_hand_type_frequencies(&deck, &rules, 10_000, 42).unwrap();
+        assert_eq!(
+            freqs,
+            estimate_hand_type_frequencies(&deck, &rules, 10_000, 42).unwrap()
+        );
+        assert!((freqs.values().sum::<f64>() - 1.0).abs() < 1e-9);
+
+        // About half of all poker hands are a high card, and four of a kind cannot be more common than one pair
+        let high_card = freqs[&HandType::HighCard];
+        assert!(high_card > 0.45 && high_card < 0.56);
+        assert!(freqs[&HandType::OnePair] > *freqs.get(&HandType::FourOfAKind).unwrap_or(&0.0));
+        assert!(!freqs.contains_key(&HandType::FiveOfAKind));
+
+        // Jokers make high cards rarer
+        let freqs_with_jokers = estimate_hand_type_frequencies(
+            &deck,
+            &GameRules::camel_cards_with_jokers(),
+            10_000,
+            42,
+        )
+        .unwrap();
+        assert!(freqs_with_jokers[&HandType::HighCard] < high_card);
+
+        // Four aces beat everything else once they are out of the deck, while the weakest possible hand loses to almost everything
+        let best = parse_input_line("AAAAK 1", &rules).unwrap();
+        assert_eq!(
+            estimate_expected_rank(&best, &deck, &rules, 5, 1_000, 7).unwrap(),
+            6.0
+        );
+        let worst = parse_input_line("23457 1", &rules).unwrap();
+        assert!(estimate_expected_rank(&worst, &deck, &rules, 5, 1_000, 7).unwrap() < 1.1);
+
+        // Five aces cannot be dealt from a standard deck, and the rest of the deck only has enough cards for nine opponents
+        let five_aces = parse_input_line("AAAAA 1", &rules).unwrap();
+        assert!(deck.without_hand(&five_aces).is_err());
+        assert!(estimate_expected_rank(&best, &deck, &rules, 9, 10, 7).is_ok());
+        assert!(estimate_expected_rank(&best, &deck, &rules, 10, 10, 7).is_err());
+
+        // Suits have to match if both the hand and the deck have them
+        let suited_deck = Deck::standard(true);
+        let hand = parse_input_line("AhAsAcAdKh 1", &rules).unwrap();
+        assert_eq!(suited_deck.without_hand(&hand).unwrap().cards.len(), 47);
+        let hand = parse_input_line("AhAhAcAdKh 1", &rules).unwrap();
+        assert!(suited_deck.without_hand(&hand).is_err());
+    }
+
+    #[test]
+    fn custom_rules() {
+        let rules = GameRules {
+            hand_size: 4,
+            card_order: ALL_CARDS.to_vec(),
+            wild_cards: vec![Card::N2],
+            hand_types: vec![
+                HandType::HighCard,
+                HandType::OnePair,
+                HandType::TwoPair,
+                HandType::ThreeOfAKind,
+                HandType::Straight,
+                HandType::Flush,
+                HandType::FourOfAKind,
+                HandType::StraightFlush,
+            ],
+            tie_break: TieBreak::Sorted,
+        };
+        let parse = |s: &str| parse_input_line(&format!("{} 1", s), &rules).unwrap();
+
+        assert_eq!(parse("9TJQ").hand_type, HandType::Straight);
+        assert_eq!(parse("9h2hJhQh").hand_type, HandType::StraightFlush);
+        assert_eq!(parse("3h9hJhQh").hand_type, HandType::Flush);
+        assert_eq!(parse("3h9hJhQd").hand_type, HandType::HighCard);
+        assert_eq!(parse("22KK").hand_type, HandType::FourOfAKind);
+        assert!(parse_input_line("9TJQK 1", &rules).is_err());
+
+        // Sorted tie-breaker compares the highest cards first
+        assert!(parse("9TJQ") < parse("TJQK"));
+        assert!(parse("A345") > parse("KQJ3"));
+        assert_eq!(parse("9TJQ"), parse("QJT9"));
+        assert_eq!(parse("A2A3").cmp(&parse("3AA2")), Ordering::Equal);
+
+        // Aces only count high in straights
+        assert_eq!(parse("A345").hand_type, HandType::HighCard);
+
+        // Incomplete rules are reported instead of panicking
+        let mut without_high_card = GameRules::camel_cards();
+        without_high_card.hand_types.remove(0);
+        assert!(parse_input_line("AKQ32 1", &without_high_card).is_err());
+        assert!(parse_input_line("AAQ32 1", &without_high_card).is_ok());
+        let mut without_aces = GameRules::camel_cards();
+        without_aces.card_order.pop();
+        assert!(parse_input_line("AKQ32 1", &without_aces).is_err());
     }
 }
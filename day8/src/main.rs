@@ -1,68 +1,501 @@
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 use num::integer::lcm;
 use regex::Regex;
-use std::collections::HashMap;
-use std::fs::read_to_string;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs::{create_dir_all, read_to_string, write};
 use std::path::Path;
 
+/// Nodes and edges (pairs of node IDs) of a part of the network.
+type Subgraph = (BTreeSet<usize>, BTreeSet<(usize, usize)>);
+
+/// Network with the node names interned to IDs, which index the dense successor table.
 struct Input {
     directions: Vec<bool>,
-    network_map: HashMap<String, (String, String)>,
+    node_names: Vec<String>,
+    node_ids: HashMap<String, usize>,
+    /// Left and right successor of every node (None if the node is referenced, but not defined in the input).
+    successors: Vec<Option<(usize, usize)>>,
+    ends_with_z: Vec<bool>,
+}
+
+impl Input {
+    fn node_id(&self, name: &str) -> Result<usize> {
+        self.node_ids
+            .get(name)
+            .cloned()
+            .with_context(|| format!("Position {} not found in input!", name))
+    }
+
+    fn next_node(&self, node: usize, go_right: bool) -> Result<usize> {
+        let (left, right) = self.successors[node]
+            .with_context(|| format!("Position {} not mapped in input!", self.node_names[node]))?;
+
+        Ok(if go_right { right } else { left })
+    }
+
+    /// Returns all A-suffixed nodes that are defined in the input (sorted by name).
+    fn start_nodes(&self) -> Vec<usize> {
+        let mut start_nodes: Vec<usize> = (0..self.node_names.len())
+            .filter(|id| self.node_names[*id].ends_with('A') && self.successors[*id].is_some())
+            .collect();
+        start_nodes.sort_by_key(|id| &self.node_names[*id]);
+        start_nodes
+    }
+
+    /// Returns the distinct successors of a node (none for nodes that are not defined).
+    fn neighbors(&self, node: usize) -> Vec<usize> {
+        match self.successors[node] {
+            Some((left, right)) if left == right => vec![left],
+            Some((left, right)) => vec![left, right],
+            None => vec![],
+        }
+    }
 }
 
 fn count_steps(input: &Input, start_pos: &str, terminate_on_z_suffix: bool) -> Result<usize> {
+    let target = if terminate_on_z_suffix {
+        None
+    } else {
+        Some(input.node_id("ZZZ")?)
+    };
     let mut step_count = 0;
-    let mut cur_pos = start_pos;
+    let mut cur_pos = input.node_id(start_pos)?;
     let mut cur_dir = input.directions.iter().cycle();
 
-    while (!terminate_on_z_suffix && cur_pos != "ZZZ")
-        || (terminate_on_z_suffix && !cur_pos.ends_with('Z'))
-    {
-        let map = input
-            .network_map
-            .get(cur_pos)
-            .with_context(|| format!("Position {} not mapped in input!", cur_pos))?;
+    while match target {
+        Some(target) => cur_pos != target,
+        None => !input.ends_with_z[cur_pos],
+    } {
+        cur_pos = input.next_node(cur_pos, *cur_dir.next().unwrap())?;
+        step_count += 1;
+    }
+
+    Ok(step_count)
+}
 
-        cur_pos = if *cur_dir.next().unwrap() {
-            &map.1
+/// Cycle in the walk of a single ghost. Since the walk is deterministic, the state (node, index in the directions) eventually repeats;
+/// all step counts are counted from the start node.
+#[derive(Debug, PartialEq)]
+struct GhostCycle {
+    /// Step at which the cycle starts.
+    offset: usize,
+    length: usize,
+    /// Steps at which a Z-suffixed node is reached before entering the cycle.
+    z_positions_before_cycle: Vec<usize>,
+    /// Steps within the first pass through the cycle at which a Z-suffixed node is reached (repeating every cycle length).
+    z_positions_in_cycle: Vec<usize>,
+}
+
+impl GhostCycle {
+    fn is_z_at(&self, step: usize) -> bool {
+        if step < self.offset {
+            self.z_positions_before_cycle.contains(&step)
         } else {
-            &map.0
-        };
+            let step_in_cycle = self.offset + (step - self.offset) % self.length;
+            self.z_positions_in_cycle.contains(&step_in_cycle)
+        }
+    }
+}
 
-        step_count += 1;
+fn analyze_cycle(input: &Input, start_pos: &str) -> Result<GhostCycle> {
+    let mut visited: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut z_positions = vec![];
+    let mut cur_pos = input.node_id(start_pos)?;
+    let mut step = 0;
+
+    loop {
+        let dir_idx = step % input.directions.len();
+        if let Some(cycle_start) = visited.insert((cur_pos, dir_idx), step) {
+            let (before, in_cycle) = z_positions.iter().partition(|s| **s < cycle_start);
+
+            return Ok(GhostCycle {
+                offset: cycle_start,
+                length: step - cycle_start,
+                z_positions_before_cycle: before,
+                z_positions_in_cycle: in_cycle,
+            });
+        }
+
+        if input.ends_with_z[cur_pos] {
+            z_positions.push(step);
+        }
+
+        cur_pos = input.next_node(cur_pos, input.directions[dir_idx])?;
+        step += 1;
     }
+}
 
-    Ok(step_count)
+/// Extended Euclidean algorithm: returns (g, x) with g = gcd(a, b) and a * x = g (mod b).
+fn extended_gcd(a: i128, b: i128) -> (i128, i128) {
+    let (mut old_r, mut r) = (a, b);
+    let (mut old_x, mut x) = (1, 0);
+
+    while r != 0 {
+        let q = old_r / r;
+        (old_r, r) = (r, old_r - q * r);
+        (old_x, x) = (x, old_x - q * x);
+    }
+
+    (old_r, old_x)
 }
 
-fn count_steps_parallel(input: &Input) -> Result<usize> {
-    let step_counts: Vec<Result<usize>> = input
-        .network_map
-        .keys()
-        .filter_map(|pos| {
-            if pos.ends_with('A') {
-                Some(count_steps(input, pos, true))
+/// Combines two congruences t = a1 (mod n1) and t = a2 (mod n2) using the Chinese Remainder Theorem (the moduli do not need to be
+/// coprime). Returns None if the congruences are incompatible.
+fn combine_congruences(
+    (a1, n1): (i128, i128),
+    (a2, n2): (i128, i128),
+) -> Result<Option<(i128, i128)>> {
+    let (g, x) = extended_gcd(n1, n2);
+    if (a2 - a1) % g != 0 {
+        return Ok(None);
+    }
+
+    let overflow = || {
+        format!(
+            "Overflow when combining cycles of length {} and {}!",
+            n1, n2
+        )
+    };
+    let lcm = (n1 / g).checked_mul(n2).with_context(overflow)?;
+    let k = ((a2 - a1) / g % (n2 / g))
+        .checked_mul(x % (n2 / g))
+        .with_context(overflow)?
+        % (n2 / g);
+    let combined = n1
+        .checked_mul(k)
+        .and_then(|n| n.checked_add(a1))
+        .with_context(overflow)?;
+    Ok(Some((combined.rem_euclid(lcm), lcm)))
+}
+
+/// Finds the first step at which all ghosts are on a Z-suffixed node at the same time.
+fn find_common_z_step(cycles: &[GhostCycle]) -> Result<usize> {
+    let max_offset = cycles.iter().map(|c| c.offset).max().unwrap_or(0);
+
+    // Before all ghosts are in their cycle, the ghost entering its cycle last can only be on a Z node at a few steps
+    if let Some(last) = cycles.iter().max_by_key(|c| c.offset) {
+        if let Some(step) = last
+            .z_positions_before_cycle
+            .iter()
+            .find(|s| cycles.iter().all(|c| c.is_z_at(**s)))
+        {
+            return Ok(*step);
+        }
+    }
+
+    // Afterwards, every ghost is on a Z node exactly at the steps matching one of its cycle's Z positions modulo the cycle length, so
+    // try every combination of those and solve the congruences
+    let mut candidates: Vec<(i128, i128)> = vec![(0, 1)];
+    for c in cycles {
+        let mut combined = vec![];
+        for cand in candidates {
+            for z in &c.z_positions_in_cycle {
+                if let Some(comb) = combine_congruences(cand, (*z as i128, c.length as i128))? {
+                    combined.push(comb);
+                }
+            }
+        }
+        candidates = combined;
+    }
+
+    let best = candidates
+        .into_iter()
+        .map(|(residue, modulus)| {
+            let max_offset = max_offset as i128;
+            if residue >= max_offset {
+                Some(residue)
             } else {
-                None
+                ((max_offset - residue + modulus - 1) / modulus)
+                    .checked_mul(modulus)
+                    .and_then(|n| n.checked_add(residue))
             }
         })
-        .collect();
-    let step_counts: Result<Vec<usize>> = step_counts.into_iter().collect();
+        .collect::<Option<Vec<_>>>()
+        .context("Overflow when moving common step count behind all cycle offsets!")?
+        .into_iter()
+        .min()
+        .context("Ghosts are never on Z-suffixed nodes at the same time!")?;
+
+    usize::try_from(best).context("Common step count does not fit into usize!")
+}
+
+/// Structural assumption about a ghost's walk that the LCM shortcut relies on, but which does not hold.
+#[derive(Debug, PartialEq)]
+enum LcmAssumptionViolation {
+    /// Cycle does not contain exactly one Z-suffixed node.
+    ZNodeCount(usize),
+    /// First Z-suffixed node is not reached after exactly one cycle length (None if no Z-suffixed node is reached at all).
+    FirstHitNotCycleLength(Option<usize>),
+    /// Z-suffixed node in the cycle is not reached at the end of the direction string.
+    NotAlignedWithDirections(usize),
+    /// Z-suffixed nodes are reached before the cycle starts.
+    ZBeforeCycle(Vec<usize>),
+    /// Z-suffixed node in the cycle is not reached after a multiple of the cycle length.
+    ZNotMultipleOfLength(usize),
+    /// Cycle starts after more steps than its length, so not every multiple of the length is in the cycle.
+    CycleStartsLate(usize),
+}
+
+fn check_lcm_assumptions(
+    cycle: &GhostCycle,
+    direction_count: usize,
+) -> Vec<LcmAssumptionViolation> {
+    let mut violations = vec![];
+
+    if cycle.z_positions_in_cycle.len() != 1 {
+        violations.push(LcmAssumptionViolation::ZNodeCount(
+            cycle.z_positions_in_cycle.len(),
+        ));
+    }
+
+    let first_hit = cycle
+        .z_positions_before_cycle
+        .iter()
+        .chain(cycle.z_positions_in_cycle.iter())
+        .next()
+        .cloned();
+    if first_hit != Some(cycle.length) {
+        violations.push(LcmAssumptionViolation::FirstHitNotCycleLength(first_hit));
+    }
+
+    for z in &cycle.z_positions_in_cycle {
+        if z % direction_count != 0 {
+            violations.push(LcmAssumptionViolation::NotAlignedWithDirections(*z));
+        }
+    }
+
+    // The LCM is only correct if the ghost is on a Z-suffixed node exactly at the multiples of the cycle length
+    if !cycle.z_positions_before_cycle.is_empty() {
+        violations.push(LcmAssumptionViolation::ZBeforeCycle(
+            cycle.z_positions_before_cycle.clone(),
+        ));
+    }
+
+    for z in &cycle.z_positions_in_cycle {
+        if z % cycle.length != 0 {
+            violations.push(LcmAssumptionViolation::ZNotMultipleOfLength(*z));
+        }
+    }
+
+    if cycle.offset > cycle.length {
+        violations.push(LcmAssumptionViolation::CycleStartsLate(cycle.offset));
+    }
+
+    violations
+}
 
-    // Due to the way the input is constructed (the distance from an A-suffixed start position to the first Z-suffixed end position is
-    // always the same as the distance from that end position to the next end position), we can just calculate the least common multiple
-    // of all the step counts.
-    let lcm = step_counts?
+/// Analyzes the cycles of all ghosts (sorted by start node).
+fn analyze_all_cycles(input: &Input) -> Result<Vec<(&String, GhostCycle)>> {
+    input
+        .start_nodes()
         .into_iter()
-        .reduce(lcm)
-        .context("Could not determine LCM!")?;
+        .map(|id| {
+            let pos = &input.node_names[id];
+            Ok((pos, analyze_cycle(input, pos)?))
+        })
+        .collect()
+}
 
-    Ok(lcm)
+/// Returns all start nodes for which the assumptions of the LCM shortcut do not hold, together with the violated assumptions.
+fn check_lcm_shortcut(input: &Input) -> Result<Vec<(String, Vec<LcmAssumptionViolation>)>> {
+    Ok(analyze_all_cycles(input)?
+        .into_iter()
+        .map(|(pos, cycle)| {
+            (
+                pos.clone(),
+                check_lcm_assumptions(&cycle, input.directions.len()),
+            )
+        })
+        .filter(|(_, violations)| !violations.is_empty())
+        .collect())
+}
+
+fn count_steps_parallel(input: &Input) -> Result<usize> {
+    let cycles: Vec<GhostCycle> = analyze_all_cycles(input)?
+        .into_iter()
+        .map(|(_, cycle)| cycle)
+        .collect();
+
+    // If the input is constructed in a way that the distance from an A-suffixed start position to the first Z-suffixed end position is
+    // always the same as the distance from that end position to the next end position, we can just calculate the least common multiple
+    // of all the cycle lengths. Otherwise, fall back to the general solution.
+    if cycles
+        .iter()
+        .all(|c| check_lcm_assumptions(c, input.directions.len()).is_empty())
+    {
+        cycles
+            .iter()
+            .map(|c| c.length)
+            .reduce(lcm)
+            .context("Could not determine LCM!")
+    } else {
+        find_common_z_step(&cycles)
+    }
+}
+
+/// Renders the network as a Graphviz DOT graph, with A-suffixed nodes in green and Z-suffixed nodes in red.
+fn export_dot(input: &Input) -> String {
+    let mut s = String::from("digraph network {\n");
+
+    for (id, name) in input.node_names.iter().enumerate() {
+        let color = if name.ends_with('A') {
+            ", style=filled, fillcolor=green"
+        } else if input.ends_with_z[id] {
+            ", style=filled, fillcolor=red"
+        } else {
+            ""
+        };
+        // Node names may contain quotes and backslashes, which have to be escaped in DOT strings
+        let label = name.replace('\\', "\\\\").replace('"', "\\\"");
+        s.push_str(&format!("    n{} [label=\"{}\"{}];\n", id, label, color));
+    }
+
+    for (id, succ) in input.successors.iter().enumerate() {
+        if let Some((left, right)) = succ {
+            s.push_str(&format!("    n{} -> n{} [label=\"L\"];\n", id, left));
+            s.push_str(&format!("    n{} -> n{} [label=\"R\"];\n", id, right));
+        }
+    }
+
+    s.push_str("}\n");
+    s
+}
+
+/// Determines the strongly connected components of the network using Kosaraju's algorithm (with iterative depth-first searches, so
+/// that long chains of nodes cannot overflow the stack).
+fn strongly_connected_components(input: &Input) -> Vec<Vec<usize>> {
+    let n = input.node_names.len();
+
+    // First pass: order nodes by finishing time
+    let mut visited = vec![false; n];
+    let mut finish_order = vec![];
+
+    for root in 0..n {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        let mut stack = vec![(root, 0)];
+
+        while let Some((node, child_idx)) = stack.pop() {
+            let neighbors = input.neighbors(node);
+            if let Some(next) = neighbors.get(child_idx) {
+                stack.push((node, child_idx + 1));
+                if !visited[*next] {
+                    visited[*next] = true;
+                    stack.push((*next, 0));
+                }
+            } else {
+                finish_order.push(node);
+            }
+        }
+    }
+
+    // Second pass: collect components on the reversed graph in reverse finishing order
+    let mut reversed = vec![vec![]; n];
+    for node in 0..n {
+        for next in input.neighbors(node) {
+            reversed[next].push(node);
+        }
+    }
+
+    let mut component_of = vec![None; n];
+    let mut components = vec![];
+
+    for root in finish_order.into_iter().rev() {
+        if component_of[root].is_some() {
+            continue;
+        }
+
+        let mut component = vec![];
+        let mut stack = vec![root];
+        component_of[root] = Some(components.len());
+
+        while let Some(node) = stack.pop() {
+            component.push(node);
+            for prev in &reversed[node] {
+                if component_of[*prev].is_none() {
+                    component_of[*prev] = Some(components.len());
+                    stack.push(*prev);
+                }
+            }
+        }
+
+        component.sort_unstable();
+        components.push(component);
+    }
+
+    components
+}
+
+/// Returns all nodes that cannot be reached from any start node (regardless of the directions).
+fn unreachable_nodes(input: &Input) -> Vec<usize> {
+    let mut reachable = vec![false; input.node_names.len()];
+    let mut queue: VecDeque<usize> = input.start_nodes().into_iter().collect();
+
+    for id in &queue {
+        reachable[*id] = true;
+    }
+
+    while let Some(node) = queue.pop_front() {
+        for next in input.neighbors(node) {
+            if !reachable[next] {
+                reachable[next] = true;
+                queue.push_back(next);
+            }
+        }
+    }
+
+    (0..input.node_names.len())
+        .filter(|id| !reachable[*id])
+        .collect()
+}
+
+/// Returns the nodes and edges that a ghost actually visits when following the directions from the given start node.
+fn ghost_subgraph(input: &Input, start_pos: &str) -> Result<Subgraph> {
+    let cycle = analyze_cycle(input, start_pos)?;
+    let mut nodes = BTreeSet::new();
+    let mut edges = BTreeSet::new();
+    let mut cur_pos = input.node_id(start_pos)?;
+    nodes.insert(cur_pos);
+
+    // Walking until the end of the first pass through the cycle covers every state the ghost will ever be in
+    for step in 0..cycle.offset + cycle.length {
+        let next_pos = input.next_node(cur_pos, input.directions[step % input.directions.len()])?;
+        edges.insert((cur_pos, next_pos));
+        nodes.insert(next_pos);
+        cur_pos = next_pos;
+    }
+
+    Ok((nodes, edges))
 }
 
 fn main() -> Result<()> {
     let input = read_input_file("../inputs/day8_input.txt")?;
 
+    println!(
+        "Strongly connected components: {}, nodes unreachable from any start node: {}",
+        strongly_connected_components(&input).len(),
+        unreachable_nodes(&input).len()
+    );
+
+    for id in input.start_nodes() {
+        let (nodes, edges) = ghost_subgraph(&input, &input.node_names[id])?;
+        println!(
+            "Ghost starting at {} visits {} nodes and {} edges",
+            input.node_names[id],
+            nodes.len(),
+            edges.len()
+        );
+    }
+
+    for (pos, violations) in check_lcm_shortcut(&input)? {
+        println!(
+            "LCM shortcut not applicable for start node {}: {:?}",
+            pos, violations
+        );
+    }
+
     println!(
         "Number of steps to reach ZZZ from AAA: {}",
         count_steps(&input, "AAA", false)?
@@ -73,6 +506,10 @@ fn main() -> Result<()> {
         count_steps_parallel(&input)?
     );
 
+    create_dir_all("../outputs")?;
+    write("../outputs/day8_network.dot", export_dot(&input))?;
+    println!("Network written to ../outputs/day8_network.dot");
+
     Ok(())
 }
 
@@ -91,31 +528,50 @@ fn read_input_file<P: AsRef<Path>>(input_path: P) -> Result<Input> {
             'R' => Some(true),
             _ => None,
         })
-        .collect();
+        .collect::<Vec<_>>();
+    ensure!(!directions.is_empty(), "No directions given!");
 
     // Skip empty line
     lines.next();
 
-    // Read mappings in network map
-    let re = Regex::new(r"([12A-Z]{3}) = \(([12A-Z]{3}), ([12A-Z]{3})\)").unwrap();
-    let mut network_map = HashMap::new();
+    // Read mappings in network map (node names may consist of any characters except whitespace, parentheses and commas)
+    let re =
+        Regex::new(r"^\s*([^\s(),=]+)\s*=\s*\(\s*([^\s(),]+)\s*,\s*([^\s(),]+)\s*\)\s*$").unwrap();
+    let mut node_names: Vec<String> = vec![];
+    let mut node_ids: HashMap<String, usize> = HashMap::new();
+    let mut successors = vec![];
 
-    for line in lines {
+    let mut intern = |name: &str| -> usize {
+        *node_ids.entry(name.to_owned()).or_insert_with(|| {
+            node_names.push(name.to_owned());
+            node_names.len() - 1
+        })
+    };
+
+    for line in lines.filter(|l| !l.trim().is_empty()) {
         let cap = re
             .captures(line)
             .with_context(|| format!("Could not parse: {}", line))?;
-        network_map.insert(
-            cap.get(1).unwrap().as_str().to_owned(),
-            (
-                cap.get(2).unwrap().as_str().to_owned(),
-                cap.get(3).unwrap().as_str().to_owned(),
-            ),
-        );
+        let node = intern(cap.get(1).unwrap().as_str());
+        let left = intern(cap.get(2).unwrap().as_str());
+        let right = intern(cap.get(3).unwrap().as_str());
+
+        let max_id = node.max(left).max(right);
+        if successors.len() <= max_id {
+            successors.resize(max_id + 1, None);
+        }
+        successors[node] = Some((left, right));
     }
+    successors.resize(node_names.len(), None);
+
+    let ends_with_z = node_names.iter().map(|n| n.ends_with('Z')).collect();
 
     Ok(Input {
         directions,
-        network_map,
+        node_names,
+        node_ids,
+        successors,
+        ends_with_z,
     })
 }
 
@@ -140,4 +596,143 @@ mod tests {
         let input = read_input_file("../inputs/day8_example3.txt").unwrap();
         assert_eq!(count_steps_parallel(&input).unwrap(), 6);
     }
+
+    #[test]
+    fn example3_cycles() {
+        let input = read_input_file("../inputs/day8_example3.txt").unwrap();
+
+        // 11A -> 11B -> 11Z -> 11B -> ..., where the state (11B, L) repeats after two steps
+        assert_eq!(
+            analyze_cycle(&input, "11A").unwrap(),
+            GhostCycle {
+                offset: 1,
+                length: 2,
+                z_positions_before_cycle: vec![],
+                z_positions_in_cycle: vec![2],
+            }
+        );
+        assert_eq!(analyze_cycle(&input, "22A").unwrap().length, 6);
+    }
+
+    #[test]
+    fn example3_lcm_assumptions() {
+        let input = read_input_file("../inputs/day8_example3.txt").unwrap();
+
+        // 11A reaches 11Z after two steps, but its cycle only starts after the first step and also has length two, so the shortcut
+        // holds; 22A reaches 22Z after three steps and then every three steps, but the cycle of (node, direction index) has length six
+        // and contains 22Z twice
+        assert_eq!(
+            check_lcm_shortcut(&input).unwrap(),
+            vec![(
+                "22A".to_owned(),
+                vec![
+                    LcmAssumptionViolation::ZNodeCount(2),
+                    LcmAssumptionViolation::FirstHitNotCycleLength(Some(3)),
+                    LcmAssumptionViolation::NotAlignedWithDirections(3),
+                    LcmAssumptionViolation::ZNotMultipleOfLength(3),
+                ]
+            )]
+        );
+
+        // Result must still be correct thanks to the fallback
+        assert_eq!(count_steps_parallel(&input).unwrap(), 6);
+
+        // 1A first reaches 1Z after two steps, which matches the length of its late cycle between 1e and 1zZ, but it is not on a
+        // Z-suffixed node after four steps, when 2A is
+        let input = read_input_file("../inputs/day8_example6.txt").unwrap();
+        assert_eq!(
+            check_lcm_shortcut(&input).unwrap(),
+            vec![(
+                "1A".to_owned(),
+                vec![
+                    LcmAssumptionViolation::ZBeforeCycle(vec![2]),
+                    LcmAssumptionViolation::CycleStartsLate(5),
+                ]
+            )]
+        );
+        assert_eq!(count_steps_parallel(&input).unwrap(), 8);
+    }
+
+    #[test]
+    fn arbitrary_node_names() {
+        let input = read_input_file("../inputs/day8_example4.txt").unwrap();
+
+        assert_eq!(input.node_names.len(), 5);
+        assert_eq!(count_steps(&input, "startA", true).unwrap(), 2);
+        assert_eq!(count_steps_parallel(&input).unwrap(), 2);
+        assert!(count_steps(&input, "AAA", false).is_err());
+
+        // Without directions, no ghost can move
+        assert!(read_input_file("../inputs/day8_example7.txt").is_err());
+    }
+
+    #[test]
+    fn example3_analysis() {
+        let input = read_input_file("../inputs/day8_example3.txt").unwrap();
+        let name = |id: &usize| input.node_names[*id].as_str();
+
+        let dot = export_dot(&input);
+        assert!(dot.contains("[label=\"11A\", style=filled, fillcolor=green]"));
+        assert!(dot.contains("[label=\"22Z\", style=filled, fillcolor=red]"));
+
+        let dot = export_dot(&read_input_file("../inputs/day8_example5.txt").unwrap());
+        assert!(dot.contains("[label=\"a\\\"A\", style=filled, fillcolor=green]"));
+        assert!(dot.contains("[label=\"b\\\\Z\", style=filled, fillcolor=red]"));
+
+        let mut components: Vec<Vec<&str>> = strongly_connected_components(&input)
+            .iter()
+            .map(|c| {
+                let mut names: Vec<_> = c.iter().map(name).collect();
+                names.sort();
+                names
+            })
+            .collect();
+        components.sort();
+        assert_eq!(
+            components,
+            vec![
+                vec!["11A"],
+                vec!["11B", "11Z"],
+                vec!["22A"],
+                vec!["22B", "22C", "22Z"],
+                vec!["XXX"]
+            ]
+        );
+
+        assert!(unreachable_nodes(&input).is_empty());
+
+        // Ghost starting at 11A never goes to XXX since it always takes the path to 11Z
+        let (nodes, edges) = ghost_subgraph(&input, "11A").unwrap();
+        let mut names: Vec<_> = nodes.iter().map(name).collect();
+        names.sort();
+        assert_eq!(names, vec!["11A", "11B", "11Z"]);
+        assert_eq!(edges.len(), 3);
+    }
+
+    #[test]
+    fn congruences() {
+        // Non-coprime moduli with and without a solution
+        assert_eq!(combine_congruences((2, 4), (4, 6)).unwrap(), Some((10, 12)));
+        assert_eq!(combine_congruences((1, 4), (2, 6)).unwrap(), None);
+
+        // Least common multiple of two coprime cycle lengths beyond 2^64 does not fit into an i128
+        assert!(combine_congruences((0, (1 << 70) - 1), (1, 1 << 70)).is_err());
+
+        // Ghost on Z every 4 steps starting at 2 and ghost on Z every 6 steps starting at 3 never meet
+        let cycles = vec![
+            GhostCycle {
+                offset: 0,
+                length: 4,
+                z_positions_before_cycle: vec![],
+                z_positions_in_cycle: vec![2],
+            },
+            GhostCycle {
+                offset: 0,
+                length: 6,
+                z_positions_before_cycle: vec![],
+                z_positions_in_cycle: vec![3],
+            },
+        ];
+        assert!(find_common_z_step(&cycles).is_err());
+    }
 }
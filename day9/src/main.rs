@@ -1,35 +1,190 @@
+use anyhow::Result;
+use num::{BigInt, BigRational, One, Signed, Zero};
+use std::fmt;
 use std::fs::read_to_string;
 use std::path::Path;
 
-fn get_next_value_for_history(n: &[i64]) -> i64 {
-    let diffs: Vec<i64> = n.windows(2).map(|w| w[1] - w[0]).collect();
+/// Minimum number of values for which a history that needs a polynomial of degree len - 1 is reported as not polynomial instead of
+/// too short.
+const MIN_LEN_FOR_NOT_POLYNOMIAL: usize = 8;
 
-    let increment = if diffs.iter().all(|d| *d == 0) {
-        // If all diffs are zero, the next value is the last (or any other) element
-        0
-    } else {
-        // Calculate increment based on next value for diff history
-        get_next_value_for_history(&diffs)
-    };
+#[derive(Debug, PartialEq)]
+enum ExtrapolationError {
+    /// History has less than two values, or its differences run out before becoming all zero and it has less than
+    /// MIN_LEN_FOR_NOT_POLYNOMIAL values. Such a history is only fitted by a polynomial of degree len - 1, which no value confirms.
+    TooShort,
+    /// Differences run out before becoming all zero even though the history has at least MIN_LEN_FOR_NOT_POLYNOMIAL values, so it
+    /// needs a polynomial of degree len - 1 (e.g., geometric growth or noise).
+    NotPolynomial,
+}
+
+impl fmt::Display for ExtrapolationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExtrapolationError::TooShort => {
+                write!(f, "History is too short to determine its degree")
+            }
+            ExtrapolationError::NotPolynomial => write!(f, "History is not polynomial"),
+        }
+    }
+}
+
+impl std::error::Error for ExtrapolationError {}
+
+/// Extrapolates a history using Newton's forward difference formula: the value at index x (relative to the first element) is the sum
+/// over all k of binomial(x, k) times the k-th difference of the first element. The difference table is computed once, and big
+/// integers are used so that evaluating far away from the history cannot overflow.
+struct Extrapolator {
+    leading_differences: Vec<BigInt>,
+    /// Number of zeros in the first level of differences that is all zero, i.e., how many values confirm the degree.
+    confirmations: usize,
+}
+
+impl Extrapolator {
+    fn new(history: &[i64]) -> Result<Extrapolator, ExtrapolationError> {
+        if history.len() < 2 {
+            return Err(ExtrapolationError::TooShort);
+        }
+
+        let mut leading_differences = vec![];
+        let mut diffs: Vec<BigInt> = history.iter().map(|v| BigInt::from(*v)).collect();
+
+        // Store the first element of each level of differences until all differences are zero
+        while !diffs.iter().all(|d| *d == BigInt::from(0)) {
+            leading_differences.push(diffs[0].clone());
+            diffs = diffs.windows(2).map(|w| &w[1] - &w[0]).collect();
+        }
+
+        // Running out of values means that the differences never became zero
+        if diffs.is_empty() {
+            return Err(if history.len() < MIN_LEN_FOR_NOT_POLYNOMIAL {
+                ExtrapolationError::TooShort
+            } else {
+                ExtrapolationError::NotPolynomial
+            });
+        }
+
+        Ok(Extrapolator {
+            leading_differences,
+            confirmations: diffs.len(),
+        })
+    }
+
+    /// Degree of the polynomial generating the history (an all-zero history is treated as constant).
+    fn degree(&self) -> usize {
+        self.leading_differences.len().saturating_sub(1)
+    }
+
+    /// Returns the value at the given index, which can be in the history, after it or before it (negative).
+    fn value_at(&self, index: i64) -> BigInt {
+        let x = BigInt::from(index);
+        let mut binomial = BigInt::from(1);
+        let mut value = BigInt::from(0);
+
+        for (k, diff) in self.leading_differences.iter().enumerate() {
+            value += &binomial * diff;
+
+            // binomial(x, k + 1) = binomial(x, k) * (x - k) / (k + 1), where the division is always exact
+            binomial = binomial * (&x - k) / (k + 1);
+        }
+
+        value
+    }
+
+    /// Expands the Newton form into the coefficients of the interpolating polynomial in the monomial basis.
+    fn polynomial(&self) -> Polynomial {
+        let mut coefficients = vec![BigRational::zero()];
+        // Coefficients of binomial(x, k), starting with binomial(x, 0) = 1
+        let mut binomial = vec![BigRational::one()];
+
+        for (k, diff) in self.leading_differences.iter().enumerate() {
+            coefficients.resize(binomial.len(), BigRational::zero());
+            for (c, b) in coefficients.iter_mut().zip(&binomial) {
+                *c += b * BigRational::from(diff.clone());
+            }
 
-    n.last().unwrap() + increment
+            // binomial(x, k + 1) = binomial(x, k) * (x - k) / (k + 1)
+            let k = BigRational::from(BigInt::from(k));
+            let mut next = vec![BigRational::zero(); binomial.len() + 1];
+            for (i, b) in binomial.iter().enumerate() {
+                next[i + 1] += b;
+                next[i] -= b * &k;
+            }
+            let divisor = &k + BigRational::one();
+            binomial = next.into_iter().map(|b| b / &divisor).collect();
+        }
+
+        Polynomial { coefficients }
+    }
+}
+
+/// Polynomial with exact rational coefficients, ordered by ascending power of x.
+struct Polynomial {
+    coefficients: Vec<BigRational>,
+}
+
+impl Polynomial {
+    /// Evaluates the polynomial at a rational point using Horner's method.
+    fn evaluate(&self, x: &BigRational) -> BigRational {
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(BigRational::zero(), |value, c| value * x + c)
+    }
 }
 
-fn get_previous_value_for_history(n: &[i64]) -> i64 {
-    let diffs: Vec<i64> = n.windows(2).map(|w| w[1] - w[0]).collect();
+impl fmt::Display for Polynomial {
+    /// Writes the polynomial with the highest power first and without zero terms, e.g. "(1/2)x^2 + (3/2)x + 1".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+
+        for (power, c) in self.coefficients.iter().enumerate().rev() {
+            if c.is_zero() {
+                continue;
+            }
 
-    let increment = if diffs.iter().all(|d| *d == 0) {
-        // If all diffs are zero, the next value is the last (or any other) element
-        0
-    } else {
-        // Calculate increment based on next value for diff history
-        get_previous_value_for_history(&diffs)
-    };
+            if first {
+                if c.is_negative() {
+                    write!(f, "-")?;
+                }
+            } else if c.is_negative() {
+                write!(f, " - ")?;
+            } else {
+                write!(f, " + ")?;
+            }
+            first = false;
 
-    n.first().unwrap() - increment
+            // Fractions in front of x are parenthesized, so that 1/2x is not read as 1/(2x)
+            let abs = c.abs();
+            if power == 0 || abs.is_integer() && !abs.is_one() {
+                write!(f, "{}", abs)?;
+            } else if !abs.is_integer() {
+                write!(f, "({})", abs)?;
+            }
+            match power {
+                0 => {}
+                1 => write!(f, "x")?,
+                _ => write!(f, "x^{}", power)?,
+            }
+        }
+
+        if first {
+            write!(f, "0")?;
+        }
+
+        Ok(())
+    }
 }
 
-fn main() {
+fn get_next_value_for_history(n: &[i64]) -> Result<BigInt> {
+    Ok(Extrapolator::new(n)?.value_at(n.len() as i64))
+}
+
+fn get_previous_value_for_history(n: &[i64]) -> Result<BigInt> {
+    Ok(Extrapolator::new(n)?.value_at(-1))
+}
+
+fn main() -> Result<()> {
     let input = read_input_file("../inputs/day9_input.txt");
 
     println!(
@@ -37,7 +192,7 @@ fn main() {
         input
             .iter()
             .map(|n| get_next_value_for_history(n))
-            .sum::<i64>()
+            .sum::<Result<BigInt>>()?
     );
 
     println!(
@@ -45,8 +200,33 @@ fn main() {
         input
             .iter()
             .map(|n| get_previous_value_for_history(n))
-            .sum::<i64>()
+            .sum::<Result<BigInt>>()?
     );
+
+    let extrapolators = input
+        .iter()
+        .map(|n| Extrapolator::new(n))
+        .collect::<Result<Vec<_>, _>>()?;
+    println!(
+        "Highest degree of any history: {}, fewest values confirming a degree: {}",
+        extrapolators.iter().map(|e| e.degree()).max().unwrap_or(0),
+        extrapolators
+            .iter()
+            .map(|e| e.confirmations)
+            .min()
+            .unwrap_or(0)
+    );
+
+    if let Some(history) = input.first() {
+        let polynomial = Extrapolator::new(history)?.polynomial();
+        println!(
+            "Polynomial of the first history: {} (next value: {})",
+            polynomial,
+            polynomial.evaluate(&BigRational::from(BigInt::from(history.len())))
+        );
+    }
+
+    Ok(())
 }
 
 fn read_input_file<P: AsRef<Path>>(input_path: P) -> Vec<Vec<i64>> {
@@ -72,8 +252,9 @@ mod tests {
             input
                 .iter()
                 .map(|n| get_next_value_for_history(n))
-                .sum::<i64>(),
-            114
+                .sum::<Result<BigInt>>()
+                .unwrap(),
+            BigInt::from(114)
         );
     }
 
@@ -84,8 +265,122 @@ mod tests {
             input
                 .iter()
                 .map(|n| get_previous_value_for_history(n))
-                .sum::<i64>(),
-            2
+                .sum::<Result<BigInt>>()
+                .unwrap(),
+            BigInt::from(2)
+        );
+    }
+
+    #[test]
+    fn extrapolate_far_away() {
+        // Squares: 0, 1, 4, 9, ...
+        let e = Extrapolator::new(&[0, 1, 4, 9]).unwrap();
+        assert_eq!(e.value_at(1000), BigInt::from(1_000_000));
+        assert_eq!(e.value_at(-1000), BigInt::from(1_000_000));
+
+        // 10^12 cubed does not fit into an i64
+        let e = Extrapolator::new(&[0, 1, 8, 27, 64]).unwrap();
+        assert_eq!(e.value_at(1_000_000_000_000), BigInt::from(10).pow(36));
+    }
+
+    #[test]
+    fn degree_detection() {
+        let input = read_input_file("../inputs/day9_example.txt");
+        let degrees: Vec<_> = input
+            .iter()
+            .map(|n| Extrapolator::new(n).unwrap().degree())
+            .collect();
+        assert_eq!(degrees, vec![1, 2, 3]);
+
+        let e = Extrapolator::new(&[0, 1, 8, 27, 64]).unwrap();
+        assert_eq!((e.degree(), e.confirmations), (3, 1));
+        assert_eq!(Extrapolator::new(&[0, 0, 0]).unwrap().degree(), 0);
+
+        assert_eq!(
+            Extrapolator::new(&[1, 2, 4, 8, 16, 32, 64, 128]).err(),
+            Some(ExtrapolationError::NotPolynomial)
+        );
+        assert_eq!(
+            Extrapolator::new(&[3, -1, 4, 1, 5, 9, 2, 6, 5]).err(),
+            Some(ExtrapolationError::NotPolynomial)
+        );
+
+        // Four cubes fit x^3 exactly, but a fifth value is needed to confirm it
+        assert_eq!(
+            Extrapolator::new(&[0, 1, 8, 27]).err(),
+            Some(ExtrapolationError::TooShort)
+        );
+        assert_eq!(
+            Extrapolator::new(&[1, 2, 4, 8, 16, 32, 64]).err(),
+            Some(ExtrapolationError::TooShort)
+        );
+        assert_eq!(
+            Extrapolator::new(&[42]).err(),
+            Some(ExtrapolationError::TooShort)
+        );
+
+        // Two values cannot confirm a linear history unless it is constant, while a long geometric history is not polynomial
+        assert_eq!(
+            Extrapolator::new(&[5, 7]).err(),
+            Some(ExtrapolationError::TooShort)
+        );
+        assert_eq!(Extrapolator::new(&[5, 5]).unwrap().degree(), 0);
+        let geometric: Vec<i64> = (0..20).map(|i| 3i64.pow(i)).collect();
+        assert_eq!(
+            Extrapolator::new(&geometric).err(),
+            Some(ExtrapolationError::NotPolynomial)
+        );
+    }
+
+    #[test]
+    fn closed_form_polynomial() {
+        let input = read_input_file("../inputs/day9_example.txt");
+        let polynomials: Vec<_> = input
+            .iter()
+            .map(|n| Extrapolator::new(n).unwrap().polynomial().to_string())
+            .collect();
+        assert_eq!(
+            polynomials,
+            vec![
+                "3x",
+                "(1/2)x^2 + (3/2)x + 1",
+                "(1/3)x^3 - x^2 + (11/3)x + 10"
+            ]
+        );
+
+        // Evaluating in between the history and checking against the sequence itself
+        let p = Extrapolator::new(&[10, 13, 16, 21, 30, 45])
+            .unwrap()
+            .polynomial();
+        let half = BigRational::new(BigInt::from(1), BigInt::from(2));
+        assert_eq!(
+            p.evaluate(&half),
+            BigRational::new(BigInt::from(93), BigInt::from(8))
+        );
+        for (i, v) in [10, 13, 16, 21, 30, 45].iter().enumerate() {
+            assert_eq!(
+                p.evaluate(&BigRational::from(BigInt::from(i))),
+                BigRational::from(BigInt::from(*v))
+            );
+        }
+
+        assert_eq!(
+            Extrapolator::new(&[5, 2, -1])
+                .unwrap()
+                .polynomial()
+                .to_string(),
+            "-3x + 5"
+        );
+        assert_eq!(
+            Extrapolator::new(&[0, -1, -3, -6])
+                .unwrap()
+                .polynomial()
+                .to_string(),
+            "-(1/2)x^2 - (1/2)x"
+        );
+        assert_eq!(
+            Extrapolator::new(&[0, 0]).unwrap().polynomial().to_string(),
+            "0"
         );
     }
 }